@@ -1,43 +1,50 @@
-/*
-use std::{sync::{Arc, Mutex, RwLock}, path::Path, fs::read_dir, io::{BufReader, Read}};
+use std::{sync::{Arc, RwLock}, path::{Path, PathBuf}, fs::read_dir};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use knowledge_search::{model::{JsonModel, GLOB_CORPUS}, indexer::{scan_dir_par, add_dir_to_corpus_parallel, add_dir_to_corpus_parallel_joined_iter, add_dir_to_corpus_parallel_merge}};
-//use knowledge_search::{model::CorpusModel, indexer::contents_by_file_type, lexer::{StrLexer, Lexer}};
+use rayon::prelude::*;
+use knowledge_search::{model::{base::Model, CorpusModel, GLOB_CORPUS}, indexer::{add_dir_to_corpus_joined, read_file_contents, FileContents}, progress::ProgressReporter};
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        }else{
+            files.push(path);
+        }
+    }
+}
 
+/// The old ingestion: every worker tokenizes and adds its document while holding the write lock.
+fn add_files_locked_per_document(files: &[PathBuf]) {
+    files.par_iter().for_each(|path| {
+        if let Ok(Some(FileContents::Parsed(parsed))) = read_file_contents(path) {
+            GLOB_CORPUS.get().unwrap().write().unwrap().add_document(path.clone(), &parsed.text);
+        }
+    });
+}
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let source = Path::new("20_newsgroups");
-    let corpus = Arc::new(RwLock::new(JsonModel::new()));
+    let corpus = Arc::new(RwLock::new(CorpusModel::new_inverted_model(Path::new("bench.index.bin"))));
     GLOB_CORPUS.set(corpus).unwrap();
 
+    let mut files = Vec::new();
+    collect_files(source, &mut files);
 
-    c.bench_function("scan_dir_par", |b| b.iter(||{
-        let local_corpus = Arc::new(JsonModel::new());
-        let _ = black_box(scan_dir_par(source, local_corpus));
-    }));
-
-    c.bench_function("add_dir_par", |b| b.iter(||{
-        {
-            GLOB_CORPUS.get().unwrap().write().unwrap().reset();
-        }
-        let _ = black_box(add_dir_to_corpus_parallel(source));
-    }));
-
-    c.bench_function("add_dir_par_joined_iter", |b| b.iter(||{
+    c.bench_function("add_dir_locked_per_document", |b| b.iter(||{
         {
             GLOB_CORPUS.get().unwrap().write().unwrap().reset();
         }
-        let _ = black_box(add_dir_to_corpus_parallel_joined_iter(source));
+        add_files_locked_per_document(black_box(&files));
     }));
 
-    c.bench_function("add_dir_par_merged", |b| b.iter(||{
+    c.bench_function("add_dir_joined_batched", |b| b.iter(||{
         {
             GLOB_CORPUS.get().unwrap().write().unwrap().reset();
         }
-        let _ = black_box(add_dir_to_corpus_parallel_merge(source));
+        let _ = black_box(add_dir_to_corpus_joined(source, &ProgressReporter::none()));
     }));
 }
 criterion_group! {name = benches; config = Criterion::default().sample_size(10); targets = criterion_benchmark}
 criterion_main!(benches);
-*/
@@ -1,5 +1,5 @@
-use std::{path::{Path, PathBuf},  fs::File, sync::{Arc, RwLock}, ops::Deref, hint::black_box};
-use knowledge_search::{indexer::add_dir_to_corpus_joined, model::{base::Model, invertedmodel::InvertedModel, json_model::JsonModel, path_to_index_name, CorpusModel, ModelType}};
+use std::{path::{Path, PathBuf},  fs::File, sync::{Arc, RwLock, mpsc::{Receiver, RecvTimeoutError}}, ops::Deref, hint::black_box, io::Write, time::{Duration, Instant}};
+use knowledge_search::{config::{get_config, Config, GLOB_CONFIG}, duplicates::near_duplicate_clusters, indexer::add_dir_to_corpus_joined, model::{base::{Model, Query, SkipReason}, path_to_index_name, CorpusModel, ModelType}, path_filter::{PathFilter, GLOB_FILTER}, progress::{ProgressEvent, ProgressReporter, ProgressStats}};
 use tracing_subscriber::{layer::SubscriberExt, Layer, util::SubscriberInitExt, filter::LevelFilter};
 use knowledge_search::tui::tui;
 
@@ -19,16 +19,57 @@ fn init_logging() {
 
 
 
-fn setup_glob_corpus(path: PathBuf){
-    let index_path = path_to_index_name(&path, ModelType::Inverted);
-    
+fn setup_glob_config(path: &Path) {
+    let config = Config::load(path);
+    GLOB_FILTER.set(PathFilter::new(path, &config)).unwrap();
+    GLOB_CONFIG.set(config).unwrap();
+}
+
+/// Draws the progress of a directory scan on stderr until the scan has finished.
+fn render_progress(rx: &Receiver<ProgressEvent>) {
+    let mut stats = ProgressStats::default();
+    let mut stderr = std::io::stderr();
+    let mut last_draw = Instant::now();
+
+    loop {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(event) => stats.apply(&event),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break
+        }
+
+        if stats.finished {
+            break;
+        }
+
+        if last_draw.elapsed() >= Duration::from_millis(100) {
+            let _ = write!(stderr, "\r{}", stats.bar(30));
+            let _ = stderr.flush();
+            last_draw = Instant::now();
+        }
+    }
 
+    let _ = writeln!(stderr, "\r{}", stats.bar(30));
+}
+
+fn load_glob_corpus(index_path: &Path) {
     //let corpus = Arc::new(RwLock::new(JsonModel::from_disk(&index_path).unwrap_or(JsonModel::new())));
-    let corpus = Arc::new(RwLock::new(CorpusModel::new_inverted_model(&index_path)));
+    let corpus = Arc::new(RwLock::new(CorpusModel::new_inverted_model(index_path)));
     GLOB_CORPUS.set(corpus).unwrap();
+}
+
+fn setup_glob_corpus(path: PathBuf){
+    let index_path = path_to_index_name(&path, ModelType::Inverted);
+    load_glob_corpus(&index_path);
+
     let start = std::time::Instant::now();
 
-    let _ = add_dir_to_corpus_joined(&path);
+    let (progress, progress_rx) = ProgressReporter::new();
+    let renderer = std::thread::spawn(move || render_progress(&progress_rx));
+    let _ = add_dir_to_corpus_joined(&path, &progress);
+    drop(progress);
+    renderer.join().unwrap();
+
     GLOB_CORPUS.get().unwrap().write().unwrap().delete_removed_files();
     let end = std::time::Instant::now();
     println!("indexing {} took {}ms", path.display(), end.duration_since(start).as_millis());
@@ -37,6 +78,7 @@ fn setup_glob_corpus(path: PathBuf){
 
 }
 
+#[allow(dead_code)]
 fn bench_alloc(path: PathBuf) {
     {
         //let model = JsonModel::from_disk(&path_to_index_name(&path, ModelType::Json)).unwrap_or(JsonModel::new());
@@ -44,11 +86,11 @@ fn bench_alloc(path: PathBuf) {
         let corpus = Arc::new(RwLock::new(model));
         GLOB_CORPUS.set(corpus).unwrap();
     }
-    let _ = black_box(add_dir_to_corpus_joined(&path));
+    let _ = black_box(add_dir_to_corpus_joined(&path, &ProgressReporter::none()));
 
     GLOB_CORPUS.get().unwrap().write().unwrap().store_with_name(&path_to_index_name(&path, ModelType::Inverted));
-    let query: Vec<char> = "Tesla".chars().collect();
-    let results: Vec<(PathBuf, f64)> = GLOB_CORPUS.get().unwrap().read().unwrap().search_simple(&query).iter().cloned().collect();
+    let query = Query::parse("Tesla");
+    let results: Vec<(PathBuf, f64)> = query.run(&*GLOB_CORPUS.get().unwrap().read().unwrap());
     println!("results: {:#?}", results);
 }
 
@@ -57,12 +99,109 @@ fn entry(path: PathBuf) {
     //bench_alloc(path);
     
     let index_path = path_to_index_name(&path, ModelType::Inverted);
-    setup_glob_corpus(path.to_path_buf());
+    setup_glob_config(&path);
+    load_glob_corpus(&index_path);
     let mut indexer = IndexerTask::new(path.clone(), index_path);
+    start_watcher(&mut indexer);
+    indexer.start_scan();
+    indexer.start_retry_queue();
 
     tui(&mut indexer).unwrap();
 }
 
+fn start_watcher(indexer: &mut IndexerTask) {
+    if let Err(e) = indexer.start_watcher() {
+        tracing::error!("Could not watch the directory for changes: {e}");
+        eprintln!("Error: could not watch the directory for changes: {e}");
+    }
+}
+
+/// Indexes the directory and keeps the index up to date with its changes without the tui,
+/// until the process is stopped.
+fn watch_entry(path: PathBuf) {
+    let index_path = path_to_index_name(&path, ModelType::Inverted);
+    setup_glob_config(&path);
+    load_glob_corpus(&index_path);
+    let mut indexer = IndexerTask::new(path.clone(), index_path);
+    let progress_rx = indexer.take_progress().unwrap();
+    start_watcher(&mut indexer);
+    indexer.start_scan();
+    indexer.start_retry_queue();
+
+    render_progress(&progress_rx);
+    println!("Watching {} for changes, press Ctrl-C to stop", path.display());
+    for event in progress_rx {
+        match event {
+            ProgressEvent::Parsed { path, .. } => println!("Indexed {}", path.display()),
+            ProgressEvent::Skipped { path, reason } => println!("Skipped {}: {reason}", path.display()),
+            ProgressEvent::Failed { path, error } => println!("Failed {}: {error}", path.display()),
+            _ => ()
+        }
+    }
+}
+
+/// Indexes the directory without starting the tui.
+fn index_entry(path: PathBuf) {
+    setup_glob_config(&path);
+    setup_glob_corpus(path);
+}
+
+/// Indexes the directory and prints the clusters of (near-)duplicate documents.
+fn dupes_entry(path: PathBuf) {
+    setup_glob_config(&path);
+    setup_glob_corpus(path);
+
+    let model = GLOB_CORPUS.get().unwrap().read().unwrap();
+    let clusters = near_duplicate_clusters(&model.simhashes(), get_config().near_duplicate_distance);
+
+    if clusters.is_empty() {
+        println!("No duplicates found");
+        return;
+    }
+
+    for (i, cluster) in clusters.iter().enumerate() {
+        let hash = model.content_hash(&cluster[0]);
+        let identical = hash.is_some() && cluster.iter().all(|p| model.content_hash(p) == hash);
+
+        println!();
+        println!("Cluster {} ({} files, {}):", i + 1, cluster.len(), if identical { "identical" }else{ "near-duplicates" });
+        for path in cluster {
+            println!("    {}", path.display());
+        }
+    }
+}
+
+/// Indexes the directory and prints the files that could not be parsed.
+fn failures_entry(path: PathBuf) {
+    setup_glob_config(&path);
+    setup_glob_corpus(path);
+
+    let mut failures: Vec<(PathBuf, SkipReason)> = GLOB_CORPUS.get().unwrap().read().unwrap().get_skipped().into_iter()
+        .filter(|(_, reason)| matches!(reason, SkipReason::Failed { .. }))
+        .collect();
+    failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    if failures.is_empty() {
+        println!("No failed files");
+        return;
+    }
+
+    println!("{} files could not be parsed:", failures.len());
+    for (path, reason) in failures {
+        println!("    {}: {reason}", path.display());
+    }
+}
+
+fn print_usage(exe: &str) {
+    eprintln!("Usage: <{exe}> [index|watch|dupes|failures] <DIRECTORY>");
+    eprintln!();
+    eprintln!("Without a command the tui is started while the directory is indexed in the background.");
+    eprintln!("    index    Index the directory and exit");
+    eprintln!("    watch    Index the directory and keep indexing its changes without the tui");
+    eprintln!("    dupes    Index the directory and list clusters of duplicate documents");
+    eprintln!("    failures Index the directory and list the files that could not be parsed");
+}
+
 
 fn main() {
 
@@ -71,20 +210,35 @@ fn main() {
     init_logging();
 
     let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: <{}> <DIRECTORY>", args.get(0).unwrap());
-        std::process::exit(1);
-    }
+    let (command, dir) = match args.len() {
+        2 => (None, args.get(1).unwrap()),
+        3 => (Some(args.get(1).unwrap().as_str()), args.get(2).unwrap()),
+        _ => {
+            print_usage(args.first().unwrap());
+            std::process::exit(1);
+        }
+    };
 
     let cwd = std::env::current_dir().unwrap();
-    let possible_dir = cwd.join(PathBuf::from(args.get(1).unwrap()));
+    let possible_dir = cwd.join(PathBuf::from(dir));
 
     if !possible_dir.exists() || !possible_dir.is_dir(){
-        eprintln!("Error: {} is not a directory", args.get(1).unwrap());
+        eprintln!("Error: {} is not a directory", dir);
         std::process::exit(2);
     }
 
     //let mut possible_dir = PathBuf::from("./20_newsgroups");
-    entry(possible_dir);
+    match command {
+        None => entry(possible_dir),
+        Some("index") => index_entry(possible_dir),
+        Some("watch") => watch_entry(possible_dir),
+        Some("dupes") => dupes_entry(possible_dir),
+        Some("failures") => failures_entry(possible_dir),
+        Some(other) => {
+            eprintln!("Error: unknown command {other}");
+            print_usage(args.first().unwrap());
+            std::process::exit(1);
+        }
+    }
 }
 
@@ -0,0 +1,138 @@
+use std::{fs::File, io::BufReader, path::Path, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::duplicates::DEFAULT_NEAR_DUPLICATE_DISTANCE;
+
+pub static GLOB_CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Name of the per directory config file. It is looked up in the root of the indexed directory.
+pub const CONFIG_FILE_NAME: &str = ".ksconfig.json";
+
+/// Default for [`Config::max_file_size`], 64 MiB.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Default for [`Config::parse_timeout_secs`].
+pub const DEFAULT_PARSE_TIMEOUT_SECS: u64 = 30;
+
+/// Default for [`Config::debounce_ms`].
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+/// Default for [`Config::poll_interval_secs`].
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// Default for [`Config::max_retries`].
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default for [`Config::retry_delay_secs`].
+pub const DEFAULT_RETRY_DELAY_SECS: u64 = 60;
+
+/// Default for [`Config::heading_boost`].
+pub const DEFAULT_HEADING_BOOST: usize = 3;
+
+/// How changes to the indexed directory are picked up while the program runs.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// The watcher of the platform (inotify on Linux). Falls back to polling when it can't be started.
+    Native,
+    /// Rescan the metadata of the directory periodically, for network mounts without change notifications.
+    Poll
+}
+
+/// Which parts of source code files are indexed, see [`crate::parser::code`].
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeMode {
+    /// Source code files are not indexed.
+    Off,
+    /// Identifiers, comments and string literals.
+    Full,
+    /// Only comments and string literals.
+    Comments
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Globs (relative to the indexed directory) a file has to match to be indexed.
+    /// An empty list includes every file.
+    pub include: Vec<String>,
+    /// Globs (relative to the indexed directory) for files and directories that are never indexed.
+    pub exclude: Vec<String>,
+    /// Files larger than this many bytes are skipped. `null` disables the limit.
+    pub max_file_size: Option<u64>,
+    /// Parsing a single file is abandoned after this many seconds. `null` disables the timeout.
+    /// The abandoned parse keeps running until it returns, see [`crate::indexer::contents_by_file_type`].
+    pub parse_timeout_secs: Option<u64>,
+    /// Hash the contents of changed files, so a file is only parsed again when its content changed.
+    pub hash_contents: bool,
+    /// Documents whose SimHash signatures differ in at most this many bits are reported as near-duplicates.
+    pub near_duplicate_distance: u32,
+    pub watch_mode: WatchMode,
+    /// Time between two scans of the directory with [`WatchMode::Poll`].
+    pub poll_interval_secs: u64,
+    /// Changes are handled once a file has not changed for this many milliseconds,
+    /// so a burst of saves is indexed once.
+    pub debounce_ms: u64,
+    /// A file that failed to parse is retried this many times while it does not change.
+    /// Changed files are always parsed again.
+    pub max_retries: u32,
+    /// Delay before the first retry of a failed file, doubled for every following retry.
+    pub retry_delay_secs: u64,
+    /// Extra count a term gets for every time it appears in a top level heading.
+    /// It is one less per heading level, so by default terms in `####` headings are not boosted.
+    pub heading_boost: usize,
+    pub code_mode: CodeMode,
+    /// Index the text outputs of the code cells of Jupyter notebooks along with their source.
+    pub notebook_outputs: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+            parse_timeout_secs: Some(DEFAULT_PARSE_TIMEOUT_SECS),
+            hash_contents: true,
+            near_duplicate_distance: DEFAULT_NEAR_DUPLICATE_DISTANCE,
+            watch_mode: WatchMode::Native,
+            poll_interval_secs: DEFAULT_POLL_INTERVAL_SECS,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay_secs: DEFAULT_RETRY_DELAY_SECS,
+            heading_boost: DEFAULT_HEADING_BOOST,
+            code_mode: CodeMode::Off,
+            notebook_outputs: false,
+        }
+    }
+}
+
+/// Returns the global config, or the default config when none was loaded.
+pub fn get_config() -> &'static Config {
+    GLOB_CONFIG.get_or_init(Config::default)
+}
+
+impl Config {
+    pub fn from_disk(config_path: &Path) -> Option<Self> {
+        let config_file = File::open(config_path).ok()?;
+
+        let res: Result<Config, serde_json::Error> = serde_json::from_reader(BufReader::new(config_file));
+        match res {
+            Ok(config) => {
+                tracing::debug!("Loaded config file {} from disk", config_path.display());
+                Some(config)
+            },
+            Err(e) => {
+                tracing::error!("Could not parse config file {}: {e}", config_path.display());
+                None
+            }
+        }
+    }
+
+    /// Loads the config file from the root of `dir`, falling back to the default config.
+    pub fn load(dir: &Path) -> Self {
+        Config::from_disk(&dir.join(CONFIG_FILE_NAME)).unwrap_or_default()
+    }
+}
@@ -0,0 +1,150 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display, path::PathBuf};
+
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::model::base::{Model, SectionLink, TermFrequency};
+
+/// Default for [`crate::config::Config::near_duplicate_distance`].
+pub const DEFAULT_NEAR_DUPLICATE_DISTANCE: u32 = 3;
+
+/// SimHash signature of a document, built from its (stemmed) terms weighted by their counts.
+/// Documents with mostly the same terms end up with signatures that differ in only a few bits.
+pub fn simhash(tf: &TermFrequency) -> u64 {
+    let mut weights = [0_i64; 64];
+
+    for (term, inner) in tf {
+        let hash = xxh3_64(term.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += inner.count as i64;
+            }else{
+                *weight -= inner.count as i64;
+            }
+        }
+    }
+
+    weights.iter().enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0, |signature, (bit, _)| signature | (1 << bit))
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// A search result together with the other paths that have exactly the same contents.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub path: PathBuf,
+    pub rank: f64,
+    pub also_at: Vec<PathBuf>,
+    /// The part of the document that matches the query best, if the document has sections.
+    pub section: Option<SectionLink>
+}
+
+impl Display for SearchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(section) = &self.section {
+            if let Some(anchor) = &section.anchor {
+                write!(f, "#{anchor}")?;
+            }
+            write!(f, " ({})", section.title)?;
+        }
+        if !self.also_at.is_empty() {
+            let paths: Vec<String> = self.also_at.iter().map(|p| p.display().to_string()).collect();
+            write!(f, " (also at: {})", paths.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collapses results with identical contents into the hit that ranks highest.
+pub fn collapse_duplicates(results: Vec<(PathBuf, f64)>, model: &dyn Model) -> Vec<SearchHit> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut hits = Vec::new();
+
+    for (path, rank) in results {
+        if seen.contains(&path) {
+            continue;
+        }
+
+        let mut also_at = model.duplicates_of(&path);
+        also_at.sort();
+        seen.extend(also_at.iter().cloned());
+        hits.push(SearchHit { path, rank, also_at, section: None });
+    }
+
+    hits
+}
+
+/// Documents with fewer distinct terms than this are left out of the near-duplicates,
+/// since the signatures of short documents are alike by chance.
+pub const MIN_SIMHASH_TERMS: usize = 20;
+
+/// Groups the documents whose signatures differ in at most `max_distance` bits from every other
+/// document of their group, so two far apart documents are never grouped through a third one.
+/// Only documents that share a band of their signature are compared: with `max_distance + 1` bands
+/// two signatures within the distance agree on at least one of them. Clusters are sorted and only
+/// contain more than one document.
+pub fn near_duplicate_clusters(simhashes: &[(PathBuf, u64, usize)], max_distance: u32) -> Vec<Vec<PathBuf>> {
+    let mut documents: Vec<(&PathBuf, u64)> = simhashes.iter()
+        .filter(|(_, _, terms)| *terms >= MIN_SIMHASH_TERMS)
+        .map(|(path, simhash, _)| (path, *simhash))
+        .collect();
+    documents.sort();
+
+    let bands = band_masks(max_distance);
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (i, (_, simhash)) in documents.iter().enumerate() {
+        for (band, mask) in bands.iter().enumerate() {
+            buckets.entry((band, simhash & mask)).or_default().push(i);
+        }
+    }
+
+    let mut clustered = vec![false; documents.len()];
+    let mut clusters = Vec::new();
+
+    for i in 0..documents.len() {
+        if clustered[i] {
+            continue;
+        }
+
+        let simhash = documents[i].1;
+        let mut candidates: Vec<usize> = bands.iter().enumerate()
+            .flat_map(|(band, mask)| &buckets[&(band, simhash & mask)])
+            .copied()
+            .filter(|j| *j > i && !clustered[*j])
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let mut members = vec![i];
+        for j in candidates {
+            if members.iter().all(|m| hamming_distance(documents[*m].1, documents[j].1) <= max_distance) {
+                members.push(j);
+            }
+        }
+
+        if members.len() > 1 {
+            for m in &members {
+                clustered[*m] = true;
+            }
+            clusters.push(members.iter().map(|m| documents[*m].0.clone()).collect());
+        }
+    }
+
+    clusters
+}
+
+/// Masks that split a signature into `max_distance + 1` bands of about equal width.
+fn band_masks(max_distance: u32) -> Vec<u64> {
+    let bands = (max_distance as usize + 1).min(64);
+    let width = 64 / bands;
+
+    (0..bands).map(|band| {
+        let start = band * width;
+        let end = if band + 1 == bands { 64 } else { start + width };
+        (start..end).fold(0, |mask, bit| mask | (1 << bit))
+    }).collect()
+}
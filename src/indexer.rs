@@ -1,50 +1,166 @@
-use std::{path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}, fs::read_dir, char};
+use std::{path::{Path, PathBuf}, fs::read_dir, sync::{Arc, atomic::{AtomicU8, AtomicUsize, Ordering}, mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender}}, thread::JoinHandle, time::Duration};
 
 use rayon::prelude::*;
-use notify::{Event, PollWatcher, Config, Watcher};
+use notify::{Event, EventKind, PollWatcher, Config, RecommendedWatcher, RecursiveMode, Watcher, event::{ModifyKind, RenameMode}};
+use notify_debouncer_full::{new_debouncer, new_debouncer_opt, DebounceEventResult, DebouncedEvent, Debouncer, FileIdMap};
 use rayon::iter::ParallelBridge;
 use crate::model::base::Model;
 
-use crate::{model::{GLOB_CORPUS, json_model::JsonModel}, parser::{txt::parse_txt, docx::get_docx_text_manual, html::{get_html_text, get_xhtml}, pdf::get_pdf, error::ParserError}, threadpool::ThreadPool};
+use crate::{config::{get_config, WatchMode}, model::{GLOB_CORPUS, ModelType, base::{AnalyzedDocument, SkipReason, hash_file, inner_path}}, parser::{sniff::{is_binary, read_head}, registry::{get_parsers, DocumentParser}, error::{ParserError, ParserErrorKind}, ParsedText}, path_filter::{GLOB_FILTER, is_dot_file, is_ignore_file}, progress::{ProgressEvent, ProgressReporter}};
+
+/// Time between two checks for failed files that are due for a retry.
+const RETRY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Parses that timed out but are still running, see [`contents_by_file_type`].
+static ABANDONED_PARSES: AtomicUsize = AtomicUsize::new(0);
+/// Timed out parses that are left running at most. Once there are this many, files are parsed without a timeout.
+pub const MAX_ABANDONED_PARSES: usize = 8;
+
+const PARSE_RUNNING: u8 = 0;
+const PARSE_DONE: u8 = 1;
+const PARSE_ABANDONED: u8 = 2;
 
 pub struct IndexerTask {
     path: PathBuf,
     index_path: PathBuf,
+    progress: ProgressReporter,
+    progress_rx: Option<Receiver<ProgressEvent>>,
+    /// Watches the directory from [`IndexerTask::start_watcher`] until the indexer is dropped.
+    watcher: Option<DirectoryWatcher>,
 
 }
 
 impl IndexerTask {
     pub fn new( path: PathBuf, index_path: PathBuf) -> Self {
-        IndexerTask { path, index_path }
+        let (progress, progress_rx) = ProgressReporter::new();
+        IndexerTask { path, index_path, progress, progress_rx: Some(progress_rx), watcher: None }
     }
 
+    /// Rescans the directory on a background thread, reporting to the progress of this indexer.
+    /// The index is stored once the scan has finished.
+    pub fn start_scan(&self) -> JoinHandle<()> {
+        let path = self.path.clone();
+        let index_path = self.index_path.clone();
+        let progress = self.progress.clone();
+
+        std::thread::spawn(move || {
+            let start = std::time::Instant::now();
+            let _ = add_dir_to_corpus_joined(&path, &progress);
+
+            let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
+            model.delete_removed_files();
+            model.store_with_name(&index_path);
+            tracing::info!("indexing {} took {}ms", path.display(), start.elapsed().as_millis());
+        })
+    }
 
-    pub fn create_watcher(&mut self) -> notify::Result<PollWatcher> {
-        let (tx, rx) = std::sync::mpsc::channel();
+    /// Parses failed files again once their retry is due, see [`crate::config::Config::max_retries`].
+    /// Files that changed are picked up by the watcher instead.
+    pub fn start_retry_queue(&self) -> JoinHandle<()> {
+        let progress = self.progress.clone();
 
-        let mut watcher = PollWatcher::new(tx, Config::default().with_manual_polling())?;
-        watcher.watch(self.path.as_ref(), notify::RecursiveMode::Recursive)?;
+        std::thread::spawn(move || loop {
+            std::thread::sleep(RETRY_CHECK_INTERVAL);
+
+            let due = GLOB_CORPUS.get().unwrap().read().unwrap().retries_due();
+            if due.is_empty() {
+                continue;
+            }
+
+            tracing::info!("Retrying {} failed files", due.len());
+            for path in &due {
+                if path.is_file() {
+                    add_file(path, &progress);
+                }else{
+                    remove_path(path);
+                }
+            }
+
+            GLOB_CORPUS.get().unwrap().write().unwrap().store();
+        })
+    }
+
+    /// Takes the receiving end of the progress events of this indexer. Can only be taken once.
+    pub fn take_progress(&mut self) -> Option<Receiver<ProgressEvent>> {
+        self.progress_rx.take()
+    }
+
+
+    /// Starts watching the directory and indexes changes on a background thread
+    /// for as long as this indexer lives, with or without the tui.
+    pub fn start_watcher(&mut self) -> notify::Result<()> {
+        let config = get_config();
+        let (tx, rx) = mpsc::channel();
+        let timeout = Duration::from_millis(config.debounce_ms);
+
+        let watcher = match config.watch_mode {
+            WatchMode::Native => match self.watch_native(timeout, tx.clone()) {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    tracing::error!("Could not start the native watcher, falling back to polling: {e}");
+                    self.watch_polling(timeout, tx)?
+                }
+            },
+            WatchMode::Poll => self.watch_polling(timeout, tx)?
+        };
+
+        let progress = self.progress.clone();
 
         std::thread::spawn(move || {
             for res in rx {
-                match res {
-                    Ok(event) => {
-                        handle_event(&event);
-                        GLOB_CORPUS.get().unwrap().write().unwrap().store();
-                    },
-                    Err(e) => tracing::error!("Watch error {e:?}")
+                let events = match res {
+                    Ok(events) => events,
+                    Err(errors) => {
+                        errors.iter().for_each(|e| tracing::error!("Watch error {e:?}"));
+                        continue;
+                    }
+                };
+
+                // Removals are handled last, so a file that was moved can still be
+                // matched by its content hash when its create is handled.
+                let (removals, changes): (Vec<DebouncedEvent>, Vec<DebouncedEvent>) = events.into_iter()
+                    .partition(|event| event.kind.is_remove());
+
+                for event in changes.iter().chain(removals.iter()) {
+                    handle_event(event, &progress);
                 }
 
+                GLOB_CORPUS.get().unwrap().write().unwrap().store();
             };
 
         });
 
-        Ok(watcher)
+        self.watcher = Some(watcher);
+        Ok(())
+
+    }
+
+    fn watch_native(&self, timeout: Duration, tx: Sender<DebounceEventResult>) -> notify::Result<DirectoryWatcher> {
+        let mut debouncer = new_debouncer(timeout, None, tx)?;
+        debouncer.watcher().watch(&self.path, RecursiveMode::Recursive)?;
+        debouncer.cache().add_root(&self.path, RecursiveMode::Recursive);
+        tracing::info!("Watching {} for changes", self.path.display());
+
+        Ok(DirectoryWatcher::Native(debouncer))
+    }
+
+    fn watch_polling(&self, timeout: Duration, tx: Sender<DebounceEventResult>) -> notify::Result<DirectoryWatcher> {
+        let interval = Duration::from_secs(get_config().poll_interval_secs);
+        let mut debouncer = new_debouncer_opt::<_, PollWatcher, FileIdMap>(timeout, None, tx, FileIdMap::new(), Config::default().with_poll_interval(interval))?;
+        debouncer.watcher().watch(&self.path, RecursiveMode::Recursive)?;
+        tracing::info!("Polling {} for changes every {}s", self.path.display(), interval.as_secs());
 
+        Ok(DirectoryWatcher::Poll(debouncer))
     }
 
 }
 
+/// Watches the indexed directory for as long as it lives.
+pub enum DirectoryWatcher {
+    Native(Debouncer<RecommendedWatcher, FileIdMap>),
+    Poll(Debouncer<PollWatcher, FileIdMap>)
+}
+
 impl Drop for IndexerTask {
     fn drop(&mut self) {
         GLOB_CORPUS.get().unwrap().write().unwrap().store_with_name(&self.index_path);
@@ -52,118 +168,368 @@ impl Drop for IndexerTask {
     }
 }
 
-pub fn is_file_supported(file: &Path) -> bool {
+pub fn contents_with_parser(parser: &dyn DocumentParser, file: &Path) -> Result<Option<ParsedText>, ParserError> {
+    let content = parser.parse(file).map_err(|e| e.with_path(file))?;
+
+    if !content.text.is_empty() || !content.documents.is_empty() {
+        return Ok(Some(content));
+    }
+
+    Ok(None)
+}
+
+pub enum FileContents {
+    Parsed(ParsedText),
+    Skipped(SkipReason)
+}
 
-    let ext_possible = file.extension();
+/// The parser for a file, or the reason the file is skipped.
+pub enum FileType {
+    Parser(&'static dyn DocumentParser),
+    Skipped(SkipReason)
+}
 
-    if let Some(ext) = ext_possible {
-        return match ext.to_str().unwrap_or("") {
-            "txt" | "docx" | "html" | "htm" | "xhtml" | "xml" | "pdf" => true,
-            _ => false
+/// Detects the type of a file by its magic bytes, with the extension as a fallback, and applies
+/// the size limit of the config. Yields `None` for files that no parser supports.
+pub fn detect_file_type(file: &Path) -> Result<Option<FileType>, ParserError> {
+    let head = read_head(file)?;
 
+    let Some(parser) = get_parsers().detect(file, &head) else {
+        if file.extension().is_none() && is_binary(&head) {
+            return Ok(Some(FileType::Skipped(SkipReason::Binary)));
         }
+        return Ok(None);
+    };
 
+    if let Some(limit) = get_config().max_file_size {
+        let size = std::fs::metadata(file)?.len();
+        if size > limit {
+            return Ok(Some(FileType::Skipped(SkipReason::TooLarge { size, limit })));
+        }
     }
 
-    false
+    Ok(Some(FileType::Parser(parser)))
 }
 
-pub fn contents_by_file_type(file: &Path) -> Result<Option<Vec<char>>, ParserError> {
-
-    let ext_possible = file.extension();
-    if let Some(ext) = ext_possible {
-        let content = match ext.to_str().unwrap_or("") {
-
-            "txt" => parse_txt(file),
-            "docx" => get_docx_text_manual(file),
-            "html" | "htm" => get_html_text(file),
-            "xhtml" | "xml" => get_xhtml(file),
-            "pdf" => get_pdf(file),
-			"" => parse_txt(file),
-            _ => {
-                tracing::info!("Filepath ({}), File type {} is currently not supported", file.display(), ext.to_str().unwrap());
-                return Ok(None);
+/// Parses a file while applying the parse timeout of the config. Parsers can't be interrupted, so a timeout
+/// only abandons the parse: its thread keeps running, and holding its memory, until the parser returns.
+/// While [`MAX_ABANDONED_PARSES`] of them are still running, files are parsed on the calling thread without a timeout,
+/// so pathological files can't pile up threads.
+pub fn contents_by_file_type(parser: &'static dyn DocumentParser, file: &Path) -> Result<Option<FileContents>, ParserError> {
+    let timeout = get_config().parse_timeout_secs.filter(|_| {
+        let abandoned = ABANDONED_PARSES.load(Ordering::Acquire);
+        if abandoned >= MAX_ABANDONED_PARSES {
+            tracing::warn!("{abandoned} timed out parses are still running, parsing {} without a timeout", file.display());
+        }
+        abandoned < MAX_ABANDONED_PARSES
+    });
+
+    let content = match timeout {
+        Some(secs) => {
+            let (tx, rx) = mpsc::channel();
+            let owned_path = file.to_path_buf();
+            let state = Arc::new(AtomicU8::new(PARSE_RUNNING));
+            let thread_state = state.clone();
+
+            std::thread::spawn(move || {
+                let _finished = ParseFinished(thread_state);
+                let _ = tx.send(contents_with_parser(parser, &owned_path));
+            });
+
+            match rx.recv_timeout(Duration::from_secs(secs)) {
+                Ok(result) => result?,
+                Err(RecvTimeoutError::Timeout) => {
+                    // Counted before the thread can see the state, so it never decrements first.
+                    ABANDONED_PARSES.fetch_add(1, Ordering::AcqRel);
+                    if state.compare_exchange(PARSE_RUNNING, PARSE_ABANDONED, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                        ABANDONED_PARSES.fetch_sub(1, Ordering::AcqRel);
+                    }
+                    return Ok(Some(FileContents::Skipped(SkipReason::Timeout { secs })));
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(ParserError::new(ParserErrorKind::Panicked).with_path(file));
+                }
             }
-        }?;
+        },
+        None => contents_with_parser(parser, file)?
+    };
 
-        if !content.is_empty(){
-            return Ok(Some(content));
+    Ok(content.map(FileContents::Parsed))
+}
+
+/// Marks a parse as done when its thread ends, also when the parser panicked.
+struct ParseFinished(Arc<AtomicU8>);
+
+impl Drop for ParseFinished {
+    fn drop(&mut self) {
+        if self.0.compare_exchange(PARSE_RUNNING, PARSE_DONE, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            ABANDONED_PARSES.fetch_sub(1, Ordering::AcqRel);
         }
     }
+}
+
+/// Reads the contents of a file while applying the size limit, type detection
+/// and parse timeout of the config.
+pub fn read_file_contents(file: &Path) -> Result<Option<FileContents>, ParserError> {
+    match detect_file_type(file)? {
+        Some(FileType::Parser(parser)) => contents_by_file_type(parser, file),
+        Some(FileType::Skipped(reason)) => Ok(Some(FileContents::Skipped(reason))),
+        None => Ok(None)
+    }
+}
 
-    Ok(None)
-    
+/// Checks if a file needs to be (re)indexed and analyzes its contents for a model of `kind`.
+/// Yields one document per file, or the documents inside it like the messages of a mailbox.
+/// Skipped files are recorded in the index and yield no documents.
+fn process_file(file_path: &Path, kind: &ModelType, progress: &ProgressReporter) -> Vec<AnalyzedDocument> {
+    // Checked before the type is detected, which reads the file, so unchanged files are only stat'ed.
+    let possible_reindex = GLOB_CORPUS.get().unwrap().read().unwrap().needs_reindex(file_path);
+
+    if let Err(e) = possible_reindex {
+        tracing::error!("Could not check if file {} needed reindexing. Error {e}", file_path.display());
+        progress.report(ProgressEvent::Discovered(file_path.to_path_buf()));
+        progress.report(ProgressEvent::Failed { path: file_path.to_path_buf(), error: e.to_string() });
+        return Vec::new();
+    }
+
+    let reindex = possible_reindex.unwrap();
+
+    if !reindex {
+        progress.report(ProgressEvent::Discovered(file_path.to_path_buf()));
+        progress.report(ProgressEvent::Unchanged(file_path.to_path_buf()));
+        return Vec::new();
+
+    }
+
+    let file_type = match detect_file_type(file_path) {
+        Ok(Some(file_type)) => file_type,
+        Ok(None) => return Vec::new(),
+        Err(err) => {
+            tracing::error!("Could not detect the type of file {}: {err}", file_path.display());
+            return Vec::new();
+        }
+    };
+
+    progress.report(ProgressEvent::Discovered(file_path.to_path_buf()));
 
+    let parser = match file_type {
+        FileType::Parser(parser) => parser,
+        FileType::Skipped(reason) => {
+            skip_file(file_path, reason, progress);
+            return Vec::new();
+        }
+    };
+
+    let hash = if get_config().hash_contents {
+        match check_content_hash(file_path, progress) {
+            Some(hash) => hash,
+            None => return Vec::new()
+        }
+    }else{
+        None
+    };
+
+    match contents_by_file_type(parser, file_path) {
+        Ok(Some(FileContents::Skipped(reason))) => {
+            skip_file(file_path, reason, progress);
+            Vec::new()
+        },
+        Ok(content) => {
+            let bytes = std::fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+            let warnings = match &content {
+                Some(FileContents::Parsed(parsed)) => parsed.warnings.len(),
+                _ => 0
+            };
+            progress.report(ProgressEvent::Parsed { path: file_path.to_path_buf(), bytes, warnings });
+
+            match content {
+                Some(FileContents::Parsed(parsed)) => {
+                    for warning in &parsed.warnings {
+                        tracing::warn!("Partially parsed {warning}");
+                    }
+                    if parsed.documents.is_empty() {
+                        vec![AnalyzedDocument::from_parsed(file_path.to_path_buf(), &parsed, kind).with_hash(hash)]
+                    }else{
+                        analyze_inner_documents(file_path, parsed, kind)
+                    }
+                },
+                _ => Vec::new()
+            }
+        },
+        Err(err) => {
+            tracing::error!("error on file {err}");
+            progress.report(ProgressEvent::Failed { path: file_path.to_path_buf(), error: format!("{}: {}", err.kind(), err.message()) });
+            GLOB_CORPUS.get().unwrap().write().unwrap().add_skipped(file_path.to_path_buf(), SkipReason::Failed { kind: err.kind().to_string(), message: err.message(), retryable: err.is_retryable() });
+            Vec::new()
+        }
+    }
 }
-fn handle_event(evn: &Event) {
-    if evn.kind.is_create() || evn.kind.is_modify() {
-        tracing::info!("event is file creation | file modification");
 
-        evn.paths.iter()
-            .filter(|p|{
-                let dot_file = p.file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false);
-                
-                let should_reindex = GLOB_CORPUS.get().unwrap().read().unwrap().needs_reindex(p);
-                if let Ok(reindex) = should_reindex {
-                    return p.is_file() && reindex && !dot_file
-                }else if let Err(e) = should_reindex{
-                    tracing::error!("{e}");
-                }
+/// Analyzes the documents inside a file at their inner paths. The documents of the previous version
+/// of the file are removed, and the file itself is recorded as their container.
+fn analyze_inner_documents(file_path: &Path, parsed: ParsedText, kind: &ModelType) -> Vec<AnalyzedDocument> {
+    {
+        let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
+        model.remove_prefix(file_path);
+        model.add_skipped(file_path.to_path_buf(), SkipReason::Container { documents: parsed.documents.len() });
+    }
 
-                false
+    parsed.documents.iter()
+        .map(|(name, document)| AnalyzedDocument::from_parsed(inner_path(file_path, name), document, kind))
+        .collect()
+}
 
-            })
-            .for_each(|p| {
-                let result = contents_by_file_type(Path::new(&p));
-                if let Ok(Some(content)) = result {
-                    let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
-                    model.add_document(p.clone(), &content);
-                }else if let Err(err) = result {
-                    tracing::error!("{err}");
+fn skip_file(file_path: &Path, reason: SkipReason, progress: &ProgressReporter) {
+    tracing::info!("Skipping file {}: {reason}", file_path.display());
+    progress.report(ProgressEvent::Skipped { path: file_path.to_path_buf(), reason: reason.clone() });
+    GLOB_CORPUS.get().unwrap().write().unwrap().add_skipped(file_path.to_path_buf(), reason);
+}
 
+/// Hashes a file whose modification time changed. Yields `None` when the file does not have to be parsed:
+/// either its content is the same as the indexed content, or it is an indexed file that was moved here,
+/// in which case the existing document is moved to the new path.
+fn check_content_hash(file_path: &Path, progress: &ProgressReporter) -> Option<Option<u64>> {
+    let hash = match hash_file(file_path) {
+        Ok(hash) => hash,
+        Err(e) => {
+            tracing::error!("Could not hash file {}: {e}", file_path.display());
+            return Some(None);
+        }
+    };
+
+    let indexed_hash = GLOB_CORPUS.get().unwrap().read().unwrap().content_hash(file_path);
+    if indexed_hash == Some(hash) {
+        tracing::info!("Contents of {} did not change, skipping reindex", file_path.display());
+        GLOB_CORPUS.get().unwrap().write().unwrap().mark_unchanged(file_path);
+        progress.report(ProgressEvent::Unchanged(file_path.to_path_buf()));
+        return None;
+    }
 
-                }
-            });
-    }else if let notify::EventKind::Remove(r) = evn.kind {
-        if r == notify::event::RemoveKind::File {
-            evn.paths.iter()
-            .for_each(|p|{
-                let dot_file = p.file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false);
-                if !dot_file {
-                    let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
-                    model.remove_document(p.clone());
+    if indexed_hash.is_none() && move_by_hash(file_path, hash) {
+        progress.report(ProgressEvent::Unchanged(file_path.to_path_buf()));
+        return None;
+    }
 
-                }
+    Some(Some(hash))
+}
 
-            });
-        }else{
-            evn.paths.iter().for_each(|p|{
-                let dot_file = p.file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false);
-                if dot_file {
-                    return;
+/// Moves an indexed document with the same content hash whose file no longer exists to `file_path`.
+fn move_by_hash(file_path: &Path, hash: u64) -> bool {
+    let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
+    let Some(from) = model.paths_with_hash(hash).into_iter().find(|p| !p.exists()) else {
+        return false;
+    };
 
-                }
-                let mut model = GLOB_CORPUS.get().unwrap().write().unwrap();
+    tracing::info!("File {} was moved to {}", from.display(), file_path.display());
+    model.rename_path(&from, file_path) > 0
+}
+
+/// Applies the ignore rules to a single walker entry, whose parents are known not to be ignored.
+fn is_entry_ignored(path: &Path, is_dir: bool) -> bool {
+    match GLOB_FILTER.get() {
+        Some(filter) => filter.is_entry_ignored(path, is_dir),
+        None => is_dot_file(path)
+    }
+}
 
-                model.get_documents().iter().filter(|(k, _)|{
-                    k.starts_with(p.clone())
+/// Applies the ignore rules to a path and all of its parents.
+fn is_path_ignored(path: &Path, is_dir: bool) -> bool {
+    match GLOB_FILTER.get() {
+        Some(filter) => filter.is_ignored(path, is_dir),
+        None => is_dot_file(path)
+    }
+}
 
-                }).for_each(|(p, _)|{
-                    model.remove_document(p.clone());
+/// Moves renamed documents in the model instead of parsing them again.
+fn handle_rename(evn: &Event, mode: RenameMode, progress: &ProgressReporter) {
+    if mode == RenameMode::Both && evn.paths.len() == 2 {
+        let (from, to) = (&evn.paths[0], &evn.paths[1]);
+        let is_dir = to.is_dir();
 
-                })
+        if is_path_ignored(to, is_dir) {
+            remove_path(from);
+            return;
+        }
 
-            });
+        let moved = GLOB_CORPUS.get().unwrap().write().unwrap().rename_path(from, to);
+        tracing::info!("Moved {moved} documents from {} to {}", from.display(), to.display());
+
+        // Files that were not indexed before the move, e.g. because they were ignored, are added now.
+        if is_dir {
+            let _ = add_dir_to_corpus_joined(to, progress);
+        }else if moved == 0 {
+            add_file(to, progress);
+        }
+        return;
+    }
 
+    // Only one side of the rename is known. A file that was moved here is matched by its content hash.
+    for path in &evn.paths {
+        if path.is_file() {
+            if !is_path_ignored(path, false) {
+                add_file(path, progress);
+            }
+        }else if path.is_dir() {
+            if !is_path_ignored(path, true) {
+                let _ = add_dir_to_corpus_joined(path, progress);
+            }
+        }else{
+            remove_path(path);
         }
+    }
+}
+
+fn add_file(path: &Path, progress: &ProgressReporter) {
+    let kind = GLOB_CORPUS.get().unwrap().read().unwrap().kind();
+    let documents = process_file(path, &kind, progress);
+    if !documents.is_empty() {
+        GLOB_CORPUS.get().unwrap().write().unwrap().add_document_batched(documents);
+    }
+}
 
+fn handle_event(evn: &Event, progress: &ProgressReporter) {
+    evn.paths.iter()
+        .filter(|p| is_ignore_file(p))
+        .for_each(|p|{
+            if let (Some(filter), Some(dir)) = (GLOB_FILTER.get(), p.parent()) {
+                tracing::info!("Ignore file {} changed, reloading its rules", p.display());
+                filter.invalidate(dir);
+            }
+        });
 
+    if let EventKind::Modify(ModifyKind::Name(mode)) = evn.kind {
+        handle_rename(evn, mode, progress);
+    }else if evn.kind.is_create() || evn.kind.is_modify() {
+        tracing::info!("event is file creation | file modification");
+
+        evn.paths.iter()
+            .filter(|p| p.is_file() && !is_path_ignored(p, false))
+            .for_each(|p| add_file(p, progress));
+    }else if evn.kind.is_remove() {
+        // The removed path can't be checked for being a directory anymore, and removing
+        // an ignored path is harmless, so every document at or below it is removed.
+        evn.paths.iter().for_each(|p| remove_path(p));
     }
 
 }
 
+fn remove_path(path: &Path) {
+    let removed = GLOB_CORPUS.get().unwrap().write().unwrap().remove_prefix(path);
+    if removed > 0 {
+        tracing::info!("Removed {removed} documents at {} from the index", path.display());
+    }
+}
+
+
+#[allow(clippy::result_unit_err)]
+pub fn add_dir_to_corpus(dir_path: &Path, progress: &ProgressReporter) -> Result<(), ()> {
+    let result = walk_dir(dir_path, progress);
+    progress.report(ProgressEvent::Finished);
+    result
+}
 
-pub fn add_dir_to_corpus(dir_path: &Path) -> Result<(), ()> {
+fn walk_dir(dir_path: &Path, progress: &ProgressReporter) -> Result<(), ()> {
+    let kind = GLOB_CORPUS.get().unwrap().read().unwrap().kind();
     //println!("processing dir: {}", dir_path.display());
 
     let dir = read_dir(dir_path).map_err(|e| {
@@ -171,7 +537,7 @@ pub fn add_dir_to_corpus(dir_path: &Path) -> Result<(), ()> {
          dir_path.display());
     })?;
 
-    let contents: Vec<(PathBuf, Vec<char>)> = dir.par_bridge().filter_map(|file|{
+    let documents: Vec<AnalyzedDocument> = dir.par_bridge().filter_map(|file|{
         let file = match file {
             Ok(f) => f,
             Err(e) => {
@@ -184,14 +550,6 @@ pub fn add_dir_to_corpus(dir_path: &Path) -> Result<(), ()> {
 
         let file_path = file.path();
 
-
-        let dot_file = file.path().file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false);
-
-        if dot_file {
-            tracing::info!("Skipping dotfile {}", file.path().display());
-            return None;
-        }
-
         match file.file_type() {
             Ok(_) => (),
             Err(e) => {
@@ -200,55 +558,77 @@ pub fn add_dir_to_corpus(dir_path: &Path) -> Result<(), ()> {
             }
         }
 
+        let is_dir = file_path.is_dir();
 
-        if file_path.is_dir() {
-            let _ = add_dir_to_corpus(&file_path);
+        if is_entry_ignored(&file_path, is_dir) {
+            tracing::info!("Skipping ignored path {}", file_path.display());
             return None;
         }
 
-        let possible_reindex = GLOB_CORPUS.get().unwrap().read().unwrap().needs_reindex(&file_path);
-
-        if let Err(e) = possible_reindex {
-            tracing::error!("Could not check if file {} needed reindexing. Error {e}", file_path.display());
+        if is_dir {
+            let _ = walk_dir(&file_path, progress);
             return None;
         }
 
-        let reindex = possible_reindex.unwrap();
-
-        if !reindex {
-            return None;
-
-        }
+        Some(process_file(&file_path, &kind, progress))
+    }).flatten_iter().collect();
 
-        let result = contents_by_file_type(Path::new(&file_path));
-        if let Err(ref err) = result {
-            tracing::error!("{err}");
-            return None;
-        }
+    GLOB_CORPUS.get().unwrap().write().unwrap().add_document_batched(documents);
 
-        if let Ok(Some(content)) = result {
-            return Some((file_path, content));
-        }
 
+    Ok(())
+}
 
-        None
 
 
-    }).collect();
+/// Number of documents a worker collects in its local batch before handing it to the ingest thread.
+pub const INGEST_BATCH_SIZE: usize = 64;
 
-    contents.par_iter().for_each(|(file_path, content)|{
-        //println!("adding file {} to corpus: ", file_path.display());
-        GLOB_CORPUS.get().unwrap().write().unwrap().add_document(file_path.clone(), &content);
+struct WalkContext<'a> {
+    progress: &'a ProgressReporter,
+    ingest: SyncSender<Vec<AnalyzedDocument>>,
+    kind: ModelType
+}
 
-    });
+/// Walks the directory in parallel. Every worker tokenizes its documents into a local batch
+/// without touching the model. Full batches are handed to a single ingest thread, which merges
+/// them into the model, so the write lock is taken once per batch instead of once per document.
+#[allow(clippy::result_unit_err)]
+pub fn add_dir_to_corpus_joined(dir_path: &Path, progress: &ProgressReporter) -> Result<(), ()> {
+    let kind = GLOB_CORPUS.get().unwrap().read().unwrap().kind();
+    let (tx, rx) = mpsc::sync_channel::<Vec<AnalyzedDocument>>(rayon::current_num_threads() * 2);
+    let ingester = std::thread::spawn(move || ingest_documents(rx));
+
+    let context = WalkContext { progress, ingest: tx, kind };
+    let result = walk_dir_joined(dir_path, &context);
+    drop(context);
+    ingester.join().unwrap();
+
+    progress.report(ProgressEvent::Finished);
+    result
+}
 
+fn ingest_documents(rx: Receiver<Vec<AnalyzedDocument>>) {
+    while let Ok(mut batch) = rx.recv() {
+        while let Ok(more) = rx.try_recv() {
+            batch.extend(more);
+        }
 
-    Ok(())
+        GLOB_CORPUS.get().unwrap().write().unwrap().add_document_batched(batch);
+    }
 }
 
+fn send_batch(context: &WalkContext, batch: Vec<AnalyzedDocument>) {
+    if batch.is_empty() {
+        return;
+    }
 
+    if context.ingest.send(batch).is_err() {
+        tracing::error!("Ingest thread stopped, documents are not added to the index");
+    }
+}
 
-pub fn add_dir_to_corpus_joined(dir_path: &Path) -> Result<(), ()> {
+fn walk_dir_joined(dir_path: &Path, context: &WalkContext) -> Result<(), ()> {
 
     let dir = read_dir(dir_path).map_err(|e| {
          tracing::error!("could not open directory {} for indexing: {e}",
@@ -268,14 +648,6 @@ pub fn add_dir_to_corpus_joined(dir_path: &Path) -> Result<(), ()> {
 
         let file_path = file.path();
 
-
-        let dot_file = file.path().file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false);
-
-        if dot_file {
-            tracing::info!("Skipping dotfile {}", file.path().display());
-            return None;
-        }
-
         match file.file_type() {
             Ok(_) => (),
             Err(e) => {
@@ -284,43 +656,26 @@ pub fn add_dir_to_corpus_joined(dir_path: &Path) -> Result<(), ()> {
             }
         }
 
+        let is_dir = file_path.is_dir();
 
-        if file_path.is_dir() {
-            let _ = add_dir_to_corpus_joined(&file_path);
-            return None;
-        }
-
-        let possible_reindex = GLOB_CORPUS.get().unwrap().read().unwrap().needs_reindex(&file_path);
-
-        if let Err(e) = possible_reindex {
-            tracing::error!("Could not check if file {} needed reindexing. Error {e}", file_path.display());
-            return None;
-        }
-
-        let reindex = possible_reindex.unwrap();
-
-        if !reindex {
+        if is_entry_ignored(&file_path, is_dir) {
+            tracing::info!("Skipping ignored path {}", file_path.display());
             return None;
-
         }
 
-        let result = contents_by_file_type(Path::new(&file_path));
-        if let Err(ref err) = result {
-            tracing::error!("error on file {}: {err}", file_path.display());
+        if is_dir {
+            let _ = walk_dir_joined(&file_path, context);
             return None;
         }
 
-        if let Ok(Some(content)) = result {
-            return Some((file_path, content));
+        Some(process_file(&file_path, &context.kind, context.progress))
+    }).fold(Vec::new, |mut batch, documents| {
+        batch.extend(documents);
+        if batch.len() >= INGEST_BATCH_SIZE {
+            send_batch(context, std::mem::take(&mut batch));
         }
-
-
-        None
-
-
-    }).for_each(|(path, content)| {
-        GLOB_CORPUS.get().unwrap().write().unwrap().add_document(path, &content);
-    });
+        batch
+    }).for_each(|batch| send_batch(context, batch));
 
 
     Ok(())
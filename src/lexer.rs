@@ -2,14 +2,19 @@ use rust_stemmers::{Stemmer, Algorithm};
 
 pub struct Lexer<'a> {
     buffer: &'a[char],
-    stemmer: Option<Stemmer>
+    stemmer: Option<Stemmer>,
+    code: bool,
+    /// Parts of the last identifier in code mode that are yet to be returned, the next one last.
+    parts: Vec<String>
 }
 
 impl<'a> Lexer<'a>{
     pub fn new(buffer: &'a [char]) -> Self {
         Lexer {
             buffer,
-            stemmer: None
+            stemmer: None,
+            code: false,
+            parts: Vec::new()
 
         }
     }
@@ -18,12 +23,30 @@ impl<'a> Lexer<'a>{
     pub fn new_stemmed(buffer: &'a [char]) -> Self {
         Lexer {
             buffer,
-            stemmer: Some(Stemmer::create(Algorithm::English))
+            stemmer: Some(Stemmer::create(Algorithm::English)),
+            code: false,
+            parts: Vec::new()
 
         }
     }
 
+    /// Tokenizes source code. Every identifier is followed by its camelCase and snake_case parts,
+    /// so `parseHttpRequest` yields `PARSEHTTPREQUEST`, `PARSE`, `HTTP` and `REQUEST`.
+    /// Identifiers are not stemmed and punctuation is skipped.
+    pub fn new_code(buffer: &'a [char]) -> Self {
+        Lexer {
+            buffer,
+            stemmer: None,
+            code: true,
+            parts: Vec::new()
+        }
+    }
+
     pub fn next_token(&mut self) -> Option<String> {
+        if self.code {
+            return self.next_code_token();
+        }
+
         self.skip_whitespaces();
         if self.buffer.is_empty(){
             return None;
@@ -54,6 +77,32 @@ impl<'a> Lexer<'a>{
         Some(self.chop(1).iter().collect::<String>().to_uppercase())
     }
 
+    fn next_code_token(&mut self) -> Option<String> {
+        if let Some(part) = self.parts.pop() {
+            return Some(part);
+        }
+
+        self.chop_while(|x| !x.is_alphanumeric() && *x != '_');
+        if self.buffer.is_empty() {
+            return None;
+        }
+        if self.buffer[0].is_numeric() {
+            return Some(self.chop_while(|x| x.is_numeric()).iter().collect());
+        }
+
+        let identifier = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
+        let parts = split_identifier(identifier);
+        let identifier = identifier.iter().collect::<String>().to_uppercase();
+
+        // A single part that is the whole identifier is not repeated, `__init__` does yield `INIT`.
+        let parts: Vec<String> = parts.iter().map(|p| p.iter().collect::<String>().to_uppercase()).collect();
+        if parts.len() > 1 || parts.first().is_some_and(|p| *p != identifier) {
+            self.parts = parts.into_iter().rev().collect();
+        }
+
+        Some(identifier)
+    }
+
     fn chop(&mut self, n: usize) -> &'a [char] {
         let token =  &self.buffer[0..n];
         self.buffer = &self.buffer[n..];
@@ -88,3 +137,90 @@ impl<'a> Iterator for Lexer<'a> {
         self.next_token()
     }
 }
+
+/// Splits an identifier at underscores and at changes from lower to upper case. A run of upper case
+/// letters is a part of its own, so `parseHTTPRequest` splits into `parse`, `HTTP` and `Request`.
+pub fn split_identifier(identifier: &[char]) -> Vec<&[char]> {
+    let mut parts = Vec::new();
+
+    for word in identifier.split(|c| *c == '_') {
+        let mut start = 0;
+        for i in 1..word.len() {
+            let (previous, c) = (word[i - 1], word[i]);
+            let next_is_lower = word.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if c.is_uppercase() && (previous.is_lowercase() || previous.is_numeric() || (previous.is_uppercase() && next_is_lower)) {
+                parts.push(&word[start..i]);
+                start = i;
+            }
+        }
+        if start < word.len() {
+            parts.push(&word[start..]);
+        }
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn parts(identifier: &str) -> Vec<String> {
+        let identifier = chars(identifier);
+        split_identifier(&identifier).iter().map(|p| p.iter().collect()).collect()
+    }
+
+    fn code_tokens(source: &str) -> Vec<String> {
+        Lexer::new_code(&chars(source)).collect()
+    }
+
+    #[test]
+    fn splits_camel_case() {
+        assert_eq!(parts("parseHttpRequest"), ["parse", "Http", "Request"]);
+        assert_eq!(parts("ParseHttpRequest"), ["Parse", "Http", "Request"]);
+    }
+
+    #[test]
+    fn keeps_upper_case_runs_together() {
+        assert_eq!(parts("parseHTTPRequest"), ["parse", "HTTP", "Request"]);
+        assert_eq!(parts("HTTP"), ["HTTP"]);
+        assert_eq!(parts("utf8Decode"), ["utf8", "Decode"]);
+    }
+
+    #[test]
+    fn splits_snake_case() {
+        assert_eq!(parts("parse_http_request"), ["parse", "http", "request"]);
+        assert_eq!(parts("MAX_FILE_SIZE"), ["MAX", "FILE", "SIZE"]);
+        assert_eq!(parts("__init__"), ["init"]);
+        assert_eq!(parts("_"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn code_tokens_follow_identifier_with_parts() {
+        assert_eq!(code_tokens("let x = parseHttpRequest(buf);"),
+            ["LET", "X", "PARSEHTTPREQUEST", "PARSE", "HTTP", "REQUEST", "BUF"]);
+    }
+
+    #[test]
+    fn code_tokens_do_not_repeat_single_part() {
+        assert_eq!(code_tokens("def __init__(self): return 42"), ["DEF", "__INIT__", "INIT", "SELF", "RETURN", "42"]);
+        assert_eq!(code_tokens("fn main() {}"), ["FN", "MAIN"]);
+    }
+
+    #[test]
+    fn unstemmed_query_matches_code_tokens() {
+        let query: Vec<String> = Lexer::new(&chars("http requests")).collect();
+        let code = code_tokens("send_http_requests()");
+        assert!(query.iter().all(|term| code.contains(term)), "{query:?} not in {code:?}");
+    }
+
+    #[test]
+    fn prose_tokens_split_punctuation() {
+        let tokens: Vec<String> = Lexer::new(&chars("Hello, world 42")).collect();
+        assert_eq!(tokens, ["HELLO", ",", "WORLD", "42"]);
+    }
+}
@@ -4,3 +4,7 @@ pub mod indexer;
 pub mod tui;
 pub mod parser;
 pub mod threadpool;
+pub mod config;
+pub mod path_filter;
+pub mod progress;
+pub mod duplicates;
@@ -1,6 +1,12 @@
-use std::{collections::HashMap, fmt::{Debug, Display}, path::{Path, PathBuf}, time::{SystemTime, SystemTimeError}};
+use std::{collections::HashMap, fmt::{Debug, Display}, fs::File, io::Read, path::{Path, PathBuf}, time::{SystemTime, SystemTimeError, UNIX_EPOCH}};
 
+use rust_stemmers::{Algorithm, Stemmer};
 use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{config::get_config, duplicates::simhash, lexer::Lexer, parser::ParsedText};
+
+use super::ModelType;
 
 pub trait Model: Debug {
 
@@ -9,8 +15,43 @@ pub trait Model: Debug {
     #[allow(dead_code)]
     fn remove_document(&mut self, path: PathBuf);
 
-    fn needs_reindex(&self, path: &PathBuf) -> Result<bool, ReindexError>;
-    fn store_with_name(&mut self, index_path: &PathBuf);
+    /// Records a file that was not indexed, so it is not retried until it changes.
+    fn add_skipped(&mut self, path: PathBuf, reason: SkipReason);
+
+    fn get_skipped(&self) -> Vec<(PathBuf, SkipReason)>;
+
+    /// Failed files whose next retry is due.
+    fn retries_due(&self) -> Vec<PathBuf>;
+
+    fn needs_reindex(&self, path: &Path) -> Result<bool, ReindexError>;
+
+    /// Content hash the document was indexed with, if hashing was enabled at the time.
+    fn content_hash(&self, path: &Path) -> Option<u64>;
+
+    /// Refreshes the modification time of a document whose content did not change.
+    fn mark_unchanged(&mut self, path: &Path);
+
+    /// Other indexed documents with the same content hash as `path`.
+    fn duplicates_of(&self, path: &Path) -> Vec<PathBuf>;
+
+    /// SimHash signatures of all indexed documents with their number of distinct terms, used to find near-duplicates.
+    fn simhashes(&self) -> Vec<(PathBuf, u64, usize)>;
+
+    /// Indexed documents with the given content hash.
+    fn paths_with_hash(&self, hash: u64) -> Vec<PathBuf>;
+
+    /// The section of the document at `path` that contains the most terms of the query.
+    fn section_of(&self, path: &Path, query: &[char]) -> Option<SectionLink>;
+
+    /// The section of the document at `path` titled `title`, ignoring case, if it contains every term of the query.
+    /// Used to search within one column of a table, one field of an email or one slide.
+    fn section_matching(&self, path: &Path, title: &str, query: &[char]) -> Option<SectionLink>;
+
+    /// Moves the document at `from`, or every document below the directory `from`, to `to`
+    /// while keeping the existing postings. Returns the number of moved documents.
+    fn rename_path(&mut self, from: &Path, to: &Path) -> usize;
+
+    fn store_with_name(&mut self, index_path: &Path);
 
     fn store(&mut self);
 
@@ -24,15 +65,21 @@ pub trait Model: Debug {
 
     fn contains_tokens_sequential(&self, pos: usize, qt: Vec<String>, doc: &Document) -> bool;
 
-    fn assert_next_token_pos(&self, doc: &Document, pos: usize, t: &String) -> bool;
+    fn assert_next_token_pos(&self, doc: &Document, pos: usize, t: &str) -> bool;
 
     fn docs_with_all_terms(&self, qt: &[String]) -> Option<Vec<PathBuf>>;
 
-    fn get_documents(&self) -> Documents;
+    fn document_count(&self) -> usize;
+
+    /// Removes the document at `path`, every document below the directory `path` and every document
+    /// inside the file `path`. Returns the number of removed documents.
+    fn remove_prefix(&mut self, path: &Path) -> usize;
 
     fn delete_removed_files(&mut self);
 
-    fn add_document_batched(&mut self, batch: Vec<(PathBuf, Vec<char>)>, dir: PathBuf);
+    /// Adds documents that were already analyzed outside of the model.
+    /// Only the merging of the term counts happens here, so the caller holds the lock for a short time.
+    fn add_document_batched(&mut self, batch: Vec<AnalyzedDocument>);
 
     fn reset(&mut self);
 }
@@ -60,12 +107,305 @@ pub struct Document {
     pub tf: TermFrequency,
     pub tf_stemmed: TermFrequency,
     pub count: usize,
-    pub last_updated: usize
+    pub last_updated: FileStamp,
+    #[serde(default)]
+    pub hash: Option<u64>,
+    #[serde(default)]
+    pub simhash: u64,
+    #[serde(default)]
+    pub sections: Vec<DocumentSection>,
+    /// Source code, whose terms are not stemmed.
+    #[serde(default)]
+    pub code: bool
+
+}
+
+/// A section of an indexed document with the distinct stemmed terms in it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct DocumentSection {
+    pub title: String,
+    pub anchor: Option<String>,
+    pub terms: Vec<String>
+}
+
+/// The part of a document a search result points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionLink {
+    pub title: String,
+    pub anchor: Option<String>
+}
+
+/// A query as it is typed by the user. `in:<section>` or `in:"<section title>"` in front of it
+/// limits the results to documents with a matching section, a query in quotes is searched as a phrase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub section: Option<String>,
+    pub text: Vec<char>,
+    pub phrase: bool
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let (section, query) = split_section_filter(query);
+        let phrase = query.len() > 2 && query.starts_with('"') && query.ends_with('"');
+        Query { section, text: query.replace('"', "").chars().collect(), phrase }
+    }
+
+    /// Ranked results of the query. With a section filter only documents whose section matches are kept.
+    pub fn run(&self, model: &dyn Model) -> Vec<(PathBuf, f64)> {
+        let mut results = if self.phrase {
+            model.search_phrase(&self.text)
+        }else{
+            model.search_simple(&self.text)
+        };
+        if let Some(section) = &self.section {
+            results.retain(|(path, _)| model.section_matching(path, section, &self.text).is_some());
+        }
+        results
+    }
+
+    /// The section of a result the query points at, the filtered section if there is one.
+    pub fn section_of(&self, model: &dyn Model, path: &Path) -> Option<SectionLink> {
+        match &self.section {
+            Some(section) => model.section_matching(path, section, &self.text),
+            None => model.section_of(path, &self.text)
+        }
+    }
+}
+
+fn split_section_filter(query: &str) -> (Option<String>, &str) {
+    let Some(rest) = query.trim_start().strip_prefix("in:") else {
+        return (None, query);
+    };
+
+    let (section, rest) = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => rest.split_once(' ').unwrap_or((rest, ""))
+    };
+    (Some(section.to_string()), rest.trim())
+}
+
+/// Terms of a query the way they are indexed for a document: stemmed for prose and unstemmed for source code.
+pub fn query_terms(query: &[char], code: bool) -> Vec<String> {
+    if code {
+        Lexer::new(query).collect()
+    }else{
+        Lexer::new_stemmed(query).collect()
+    }
+}
+
+/// Picks the section that contains the most of the distinct `query_terms`, the first one on a tie.
+/// Returns `None` when no section contains any of them.
+pub fn best_section<'a, S>(sections: &'a [S], query_terms: &[String], contains: impl Fn(&S, &str) -> bool) -> Option<&'a S> {
+    let mut best = None;
+    let mut best_matches = 0;
+
+    for section in sections {
+        let matches = query_terms.iter().filter(|t| contains(section, t)).count();
+        if matches > best_matches {
+            best = Some(section);
+            best_matches = matches;
+        }
+    }
+
+    best
+}
 
+/// A document whose term counts are computed without access to the model.
+#[derive(Debug, Clone)]
+pub struct AnalyzedDocument {
+    pub path: PathBuf,
+    pub document: Document
 }
 
+impl AnalyzedDocument {
+    /// Tokenizes the content for a model of `kind`. The inverted model only needs
+    /// the stemmed counts, so the unstemmed terms and positions are left empty for it.
+    pub fn new(path: PathBuf, content: &[char], kind: &ModelType) -> Self {
+        AnalyzedDocument::tokenize(path, content, kind, false)
+    }
+
+    /// Tokenizes source code like [`AnalyzedDocument::new`], but without stemming, see [`Lexer::new_code`].
+    pub fn new_code(path: PathBuf, content: &[char], kind: &ModelType) -> Self {
+        AnalyzedDocument::tokenize(path, content, kind, true)
+    }
+
+    fn tokenize(path: PathBuf, content: &[char], kind: &ModelType, code: bool) -> Self {
+        let mut tf = TermFrequency::new();
+        let mut tf_stemmed = TermFrequency::new();
+        let mut count: usize = 0;
+
+        match kind {
+            ModelType::Json => {
+                let stemmer = (!code).then(|| Stemmer::create(Algorithm::English));
+                let lexer = if code { Lexer::new_code(content) } else { Lexer::new(content) };
+
+                for (pos, token) in lexer.enumerate() {
+                    let stemmed = match &stemmer {
+                        Some(stemmer) => stemmer.stem(token.as_str()).to_string(),
+                        None => token.clone()
+                    };
+
+                    if let Some(t) = tf.get_mut(token.as_str()){
+                        t.count += 1;
+                        t.positions.push(pos);
+
+                    }else{
+                        tf.insert(token.clone(), TermInner::new(pos));
+                    }
+
+                    if let Some(t) = tf_stemmed.get_mut(&stemmed){
+                        t.count += 1;
+                        t.positions.push(pos);
+
+                    }else{
+                        tf_stemmed.insert(stemmed, TermInner::new(pos));
+                    }
+                    count += 1;
+                }
+            },
+            ModelType::Inverted => {
+                let lexer = if code { Lexer::new_code(content) } else { Lexer::new_stemmed(content) };
+                for token in lexer {
+                    tf_stemmed.entry(token).or_insert(TermInner { count: 0, positions: Vec::new() }).count += 1;
+                    count += 1;
+                }
+            }
+        }
+
+        let last_updated = FileStamp::of(&path);
+        let simhash = simhash(&tf_stemmed);
+        AnalyzedDocument { path, document: Document { tf, tf_stemmed, count, last_updated, hash: None, simhash, sections: Vec::new(), code } }
+    }
+
+    /// Tokenizes parsed text like [`AnalyzedDocument::new`], and records its sections.
+    /// Terms in the titles of headings are counted again, see [`crate::config::Config::heading_boost`].
+    pub fn from_parsed(path: PathBuf, parsed: &ParsedText, kind: &ModelType) -> Self {
+        let mut analyzed = if parsed.code {
+            AnalyzedDocument::new_code(path, &parsed.text, kind)
+        }else{
+            AnalyzedDocument::new(path, &parsed.text, kind)
+        };
+        let document = &mut analyzed.document;
+        let boost = get_config().heading_boost;
+
+        for (i, section) in parsed.sections.iter().enumerate() {
+            let end = parsed.sections.get(i + 1).map(|s| s.start).unwrap_or(parsed.text.len());
+            let text = &parsed.text[section.start.min(end)..end];
+            let lexer = if parsed.code { Lexer::new_code(text) } else { Lexer::new_stemmed(text) };
+            let mut terms: Vec<String> = lexer.collect();
+            terms.sort();
+            terms.dedup();
+            document.sections.push(DocumentSection { title: section.title.clone(), anchor: section.anchor.clone(), terms });
+
+            let extra = match section.level {
+                Some(level) => boost.saturating_sub(level.saturating_sub(1) as usize),
+                None => 0
+            };
+            if extra == 0 {
+                continue;
+            }
+
+            // Only terms of the text are boosted, so every term keeps the positions of its real occurrences.
+            // The extra occurrences count towards the length of the document, which keeps tf at most 1.
+            let title: Vec<char> = section.title.chars().collect();
+            for token in Lexer::new_stemmed(&title) {
+                if let Some(term) = document.tf_stemmed.get_mut(&token) {
+                    term.count += extra;
+                    document.count += extra;
+                }
+            }
+            if *kind == ModelType::Json {
+                for token in Lexer::new(&title) {
+                    if let Some(term) = document.tf.get_mut(&token) {
+                        term.count += extra;
+                    }
+                }
+            }
+        }
+
+        analyzed
+    }
+
+    pub fn with_hash(mut self, hash: Option<u64>) -> Self {
+        self.document.hash = hash;
+        self
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    TooLarge { size: u64, limit: u64 },
+    Binary,
+    Timeout { secs: u64 },
+    /// The parser returned an error of `kind`. Only retryable failures are parsed again while the file is unchanged.
+    Failed { kind: String, message: String, #[serde(default)] retryable: bool },
+    /// The file holds separate documents, like the messages of a mailbox, which are indexed at their [`inner_path`].
+    Container { documents: usize }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::TooLarge { size, limit } => write!(f, "file size of {size} bytes exceeds the limit of {limit} bytes"),
+            SkipReason::Binary => write!(f, "file contains binary data"),
+            SkipReason::Timeout { secs } => write!(f, "parsing took longer than {secs}s"),
+            SkipReason::Failed { kind, message, .. } => write!(f, "{kind}: {message}"),
+            SkipReason::Container { documents } => write!(f, "file holds {documents} documents, indexed separately"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SkippedDocument {
+    pub reason: SkipReason,
+    pub last_updated: FileStamp,
+    /// Number of times in a row parsing the unchanged file failed.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Unix time after which a failed file is parsed again, even if it did not change.
+    #[serde(default)]
+    pub retry_at: Option<u64>
+}
+
+impl SkippedDocument {
+    /// Failures of the same unchanged file as `previous` count as another attempt. Retryable failures are
+    /// retried with an exponential backoff until `max_retries` attempts failed.
+    pub fn new(path: &Path, reason: SkipReason, previous: Option<&SkippedDocument>) -> Self {
+        let last_updated = FileStamp::of(path);
+        let mut attempts = 0;
+        let mut retry_at = None;
+
+        if matches!(reason, SkipReason::Failed { .. }) {
+            attempts = match previous {
+                Some(p) if p.last_updated == last_updated => p.attempts + 1,
+                _ => 1
+            };
+
+            let config = get_config();
+            if matches!(reason, SkipReason::Failed { retryable: true, .. }) && attempts <= config.max_retries {
+                let delay = config.retry_delay_secs.saturating_mul(1 << (attempts - 1).min(16));
+                retry_at = Some(now_secs().saturating_add(delay));
+            }
+        }
+
+        SkippedDocument { reason, last_updated, attempts, retry_at }
+    }
+
+    pub fn is_retry_due(&self) -> bool {
+        self.retry_at.is_some_and(|t| now_secs() >= t)
+    }
+}
+
+/// Current time in whole seconds since the unix epoch.
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub type SkippedDocuments = HashMap<PathBuf, SkippedDocument>;
+
 #[inline(always)]
-pub fn calculate_tf(d: &Document, t: &String, stemmed: bool) -> f64 {
+pub fn calculate_tf(d: &Document, t: &str, stemmed: bool) -> f64 {
     let n = d.count as f64;
     let tf = if stemmed {
         &d.tf_stemmed
@@ -85,7 +425,7 @@ pub fn calculate_tf(d: &Document, t: &String, stemmed: bool) -> f64 {
 
 }
 #[inline(always)]
-pub fn calculate_idf(t: &String, n: usize, df: &DocumentFrequency) -> f64 {
+pub fn calculate_idf(t: &str, n: usize, df: &DocumentFrequency) -> f64 {
     let n = n as f64;
     let f = df.get(t).cloned().unwrap_or(1) as f64;
     (n / f).log10()
@@ -94,13 +434,96 @@ pub fn calculate_idf(t: &String, n: usize, df: &DocumentFrequency) -> f64 {
 }
 
 #[inline(always)]
-pub fn get_last_modified(path: &PathBuf) -> Result<SystemTime, std::io::Error> {
-        let metadata = std::fs::metadata(path)?;
+pub fn get_last_modified(path: &Path) -> Result<SystemTime, std::io::Error> {
+        let metadata = std::fs::metadata(file_of(path))?;
         let modified = metadata.modified()?;
         Ok(modified)
 
 }
 
+/// Modification time and size of a file when it was indexed. The time has sub-second precision,
+/// so an edit within the same second as the indexed version is noticed too.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileStamp {
+    /// Nanoseconds since the unix epoch.
+    pub modified: u64,
+    pub size: u64
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> Result<Self, std::io::Error> {
+        let metadata = std::fs::metadata(file_of(path))?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        Ok(FileStamp { modified, size: metadata.len() })
+    }
+
+    /// The stamp of the file, or the default when it can't be read.
+    pub fn of(path: &Path) -> Self {
+        FileStamp::read(path).unwrap_or_default()
+    }
+}
+
+/// Hashes the raw bytes of a file with xxh3, reading it in chunks.
+pub fn hash_file(path: &Path) -> Result<u64, std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher.digest())
+}
+
+/// Path of `path` after the file or directory `from` was moved to `to`.
+pub fn renamed_path(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    if let Some((file, inner)) = split_inner_path(path) {
+        if !path.starts_with(from) {
+            return renamed_path(&file, from, to).map(|file| inner_path(&file, &inner));
+        }
+    }
+
+    path.strip_prefix(from).ok().map(|rest| {
+        if rest.as_os_str().is_empty() {
+            to.to_path_buf()
+        }else{
+            to.join(rest)
+        }
+    })
+}
+
+/// Separates the path of a file from the path of a document inside it, like a message of a mailbox: `inbox.mbox!/3`.
+pub const INNER_SEPARATOR: &str = "!/";
+
+/// Path of the document `name` inside `file`.
+pub fn inner_path(file: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}{INNER_SEPARATOR}{name}", file.display()))
+}
+
+/// The file holding a document inside a file and the path of the document inside it.
+pub fn split_inner_path(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    path.split_once(INNER_SEPARATOR).map(|(file, inner)| (PathBuf::from(file), inner.to_string()))
+}
+
+/// The file on disk of a document, which is the file holding it for documents inside a file.
+pub fn file_of(path: &Path) -> PathBuf {
+    match split_inner_path(path) {
+        Some((file, _)) if !path.exists() => file,
+        _ => path.to_path_buf()
+    }
+}
+
+/// Whether `path` is `prefix`, below it, or a document inside a file at or below it.
+pub fn is_within(path: &Path, prefix: &Path) -> bool {
+    path.starts_with(prefix) || split_inner_path(path).is_some_and(|(file, _)| file.starts_with(prefix))
+}
+
 #[derive(Debug)]
 pub enum ReindexError {
     SystemTimeError(SystemTimeError),
@@ -131,3 +554,27 @@ impl Display for ReindexError {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(section: Option<&str>, text: &str, phrase: bool) -> Query {
+        Query { section: section.map(str::to_string), text: text.chars().collect(), phrase }
+    }
+
+    #[test]
+    fn parses_plain_and_phrase_queries() {
+        assert_eq!(Query::parse("alice bob"), query(None, "alice bob", false));
+        assert_eq!(Query::parse("\"alice bob\""), query(None, "alice bob", true));
+    }
+
+    #[test]
+    fn parses_section_filters() {
+        assert_eq!(Query::parse("in:owner alice"), query(Some("owner"), "alice", false));
+        assert_eq!(Query::parse("  in:\"Cell 4\" groupby"), query(Some("Cell 4"), "groupby", false));
+        assert_eq!(Query::parse("in:subject \"due invoice\""), query(Some("subject"), "due invoice", true));
+        assert_eq!(Query::parse("in:owner"), query(Some("owner"), "", false));
+        assert_eq!(Query::parse("alice in:owner"), query(None, "alice in:owner", false));
+    }
+}
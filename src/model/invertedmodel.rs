@@ -1,23 +1,36 @@
 use core::f64;
-use std::{collections::HashMap, fs::File, io::{BufReader, BufWriter, Write}, path::PathBuf, time::UNIX_EPOCH, usize};
+use std::{collections::HashMap, fs::File, io::{BufReader, BufWriter, Write}, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
 
-use crate::lexer::Lexer;
-
-use super::base::{Model, get_last_modified};
+use super::{ModelType, base::{AnalyzedDocument, Model, SectionLink, best_section, query_terms, SkipReason, SkippedDocument, SkippedDocuments, FileStamp, renamed_path, file_of, is_within}};
 
 
 pub type TF = f64;
 
+/// Compact id of a document. Postings are keyed by it instead of by the full path.
+pub type DocId = u32;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct InvertedModelDocumentMeta {
     path: PathBuf,
-    //terms: HashSet<String>, //easy lookup for file deletion. Tho it can probably be removed as it
-                            //would be better to just iterate over each term and check if it
-                            //includes the document we are trying to delete
-    last_updated: usize
+    /// Forward index of the document, so removing it only touches the postings of its own terms.
+    terms: Vec<String>,
+    last_updated: FileStamp,
+    hash: Option<u64>,
+    simhash: u64,
+    sections: Vec<InvertedSectionMeta>,
+    /// Source code, whose terms are not stemmed.
+    code: bool
+
+}
 
+/// A section of a document, with its terms as indices into the forward index of the document.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct InvertedSectionMeta {
+    title: String,
+    anchor: Option<String>,
+    terms: Vec<u32>
 }
 //TODO: split up model interface in to 2 seperate ones
 // One for model management (adding, deleting and updating files)
@@ -29,8 +42,13 @@ pub struct InvertedModelDocumentMeta {
 pub struct InvertedModel {
     path: Option<PathBuf>,
     count: usize,
-    term_frequency: HashMap<String, HashMap<PathBuf, TF>>,
-    documents_meta: HashMap<PathBuf, InvertedModelDocumentMeta>,
+    term_frequency: HashMap<String, HashMap<DocId, TF>>,
+    documents_meta: HashMap<DocId, InvertedModelDocumentMeta>,
+    doc_ids: HashMap<PathBuf, DocId>,
+    next_doc_id: DocId,
+    skipped: SkippedDocuments,
+    /// Documents by content hash, to find files with identical contents.
+    hashes: HashMap<u64, Vec<DocId>>,
 
 }
 
@@ -39,99 +57,192 @@ impl InvertedModel {
         InvertedModel {
             term_frequency: HashMap::new(),
             documents_meta: HashMap::new(),
+            doc_ids: HashMap::new(),
+            next_doc_id: 0,
+            skipped: SkippedDocuments::new(),
+            hashes: HashMap::new(),
             path: None,
             count: 0
         }
 
     }
 
-}
+    fn get_meta(&self, path: &Path) -> Option<&InvertedModelDocumentMeta> {
+        self.doc_ids.get(path).and_then(|id| self.documents_meta.get(id))
+    }
 
-impl Model for InvertedModel {
-    fn add_document(&mut self, path: PathBuf, content: &[char]) {
-        if self.documents_meta.contains_key(&path) {
-            self.count -= 1;
-            for (_, freq) in &mut self.term_frequency {
-                if freq.contains_key(&path) {
-                    freq.remove(&path).unwrap();
+    /// Removes the document with `id` and its postings, using the forward index.
+    /// Terms without any postings left are pruned, so the document frequencies stay exact.
+    fn remove_by_id(&mut self, id: DocId) -> Option<InvertedModelDocumentMeta> {
+        let meta = self.documents_meta.remove(&id)?;
+        self.doc_ids.remove(&meta.path);
+        self.count -= 1;
+
+        if let Some(hash) = meta.hash {
+            if let Some(ids) = self.hashes.get_mut(&hash) {
+                ids.retain(|i| *i != id);
+                if ids.is_empty() {
+                    self.hashes.remove(&hash);
                 }
             }
         }
 
-        let doc_meta = InvertedModelDocumentMeta{
-            path: path.clone(),
-            //terms: HashSet::new(),
-            last_updated: get_last_modified(&path).unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize,
-        };
+        for term in &meta.terms {
+            if let Some(freq) = self.term_frequency.get_mut(term) {
+                freq.remove(&id);
+                if freq.is_empty() {
+                    self.term_frequency.remove(term);
+                }
+            }
+        }
 
-        
-        let lexer = Lexer::new_stemmed(content);
-        let terms: Vec<String> = lexer.collect();
-        //doc_meta.terms = HashSet::from_iter(terms.iter().cloned());
-        let count: usize = terms.len();
+        Some(meta)
+    }
+
+    /// Number of distinct terms in the index.
+    pub fn term_count(&self) -> usize {
+        self.term_frequency.len()
+    }
 
-        let mut tf = HashMap::<String, usize>::new();
+}
 
-        for term in terms {
-            if let Some(entry) = tf.get_mut(&term) {
-                *entry += 1;
-            }else{
-                tf.insert(term.clone(), 1);
-            }
+impl Default for InvertedModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Model for InvertedModel {
+    fn add_document(&mut self, path: PathBuf, content: &[char]) {
+        self.add_document_batched(vec![AnalyzedDocument::new(path, content, &ModelType::Inverted)]);
+    }
+
+    fn remove_document(&mut self, path: PathBuf) {
+        self.skipped.remove(&path);
+        if let Some(id) = self.doc_ids.get(&path).copied() {
+            self.remove_by_id(id);
         }
+    }
 
-        
+    fn add_skipped(&mut self, path: PathBuf, reason: SkipReason) {
+        let previous = self.skipped.get(&path).cloned();
+        self.remove_document(path.clone());
+        let skipped = SkippedDocument::new(&path, reason, previous.as_ref());
+        self.skipped.insert(path, skipped);
+    }
 
-        for (term, freq) in tf {
-            if let Some(entry) = self.term_frequency.get_mut(&term) {
-                if !entry.contains_key(&path) {
-                    entry.insert(path.clone(), freq as f64 / count as f64);
-                }
-            }else{
-                let mut inner_map: HashMap<PathBuf, TF> = HashMap::new();
-                inner_map.insert(path.clone(), freq as f64 / count as f64);
-                self.term_frequency.insert(term.clone(), inner_map);
-            }
+    fn get_skipped(&self) -> Vec<(PathBuf, SkipReason)> {
+        self.skipped.iter().map(|(p, s)| (p.clone(), s.reason.clone())).collect()
+    }
 
+    fn retries_due(&self) -> Vec<PathBuf> {
+        self.skipped.iter().filter(|(_, s)| s.is_retry_due()).map(|(p, _)| p.clone()).collect()
+    }
 
-        }
+    fn needs_reindex(&self, path: &Path) -> Result<bool, super::base::ReindexError> {
+        let last_updated = if let Some(meta) = self.get_meta(path) {
+            meta.last_updated
+        }else if let Some(skipped) = self.skipped.get(path) {
+            if skipped.is_retry_due() {
+                return Ok(true);
+            }
+            skipped.last_updated
+        }else{
+            return Ok(true)
+        };
 
-        self.documents_meta.insert(path.clone(), doc_meta);
-        self.count += 1;
+        Ok(FileStamp::read(path)? != last_updated)
 
 
+    }
 
+    fn content_hash(&self, path: &Path) -> Option<u64> {
+        self.get_meta(path).and_then(|meta| meta.hash)
     }
 
-    fn remove_document(&mut self, path: PathBuf) {
-        if let Some(curr) = self.documents_meta.remove(&path) {
-            for (_, freq) in &mut self.term_frequency {
-                if freq.contains_key(&path) {
-                    freq.remove(&path).unwrap();
-                }
-            }
+    fn mark_unchanged(&mut self, path: &Path) {
+        if let Some(meta) = self.doc_ids.get(path).and_then(|id| self.documents_meta.get_mut(id)) {
+            meta.last_updated = FileStamp::of(path);
         }
     }
 
-    fn needs_reindex(&self, path: &PathBuf) -> Result<bool, super::base::ReindexError> {
-        if !self.documents_meta.contains_key(path) {
-            return Ok(true)
-        }
+    fn duplicates_of(&self, path: &Path) -> Vec<PathBuf> {
+        let Some(hash) = self.content_hash(path) else {
+            return Vec::new();
+        };
 
-        let last_updated = self.documents_meta.get(path).unwrap().last_updated;
+        self.hashes.get(&hash).into_iter().flatten()
+            .filter_map(|id| self.documents_meta.get(id))
+            .filter(|meta| meta.path != path)
+            .map(|meta| meta.path.clone())
+            .collect()
+    }
 
-        let file_last_updated = get_last_modified(path)?.duration_since(UNIX_EPOCH).unwrap().as_secs() as usize;
+    fn simhashes(&self) -> Vec<(PathBuf, u64, usize)> {
+        self.documents_meta.values().map(|meta| (meta.path.clone(), meta.simhash, meta.terms.len())).collect()
+    }
 
-        Ok(file_last_updated > last_updated)
+    fn paths_with_hash(&self, hash: u64) -> Vec<PathBuf> {
+        self.hashes.get(&hash).into_iter().flatten()
+            .filter_map(|id| self.documents_meta.get(id))
+            .map(|meta| meta.path.clone())
+            .collect()
+    }
 
+    fn section_of(&self, path: &Path, query: &[char]) -> Option<SectionLink> {
+        let meta = self.get_meta(path)?;
+        let query_terms = query_terms(query, meta.code);
 
+        best_section(&meta.sections, &query_terms, |section, term| {
+            section.terms.iter().any(|i| meta.terms[*i as usize] == term)
+        }).map(|section| SectionLink { title: section.title.clone(), anchor: section.anchor.clone() })
+    }
+
+    fn section_matching(&self, path: &Path, title: &str, query: &[char]) -> Option<SectionLink> {
+        let meta = self.get_meta(path)?;
+        let query_terms = query_terms(query, meta.code);
+
+        meta.sections.iter()
+            .filter(|section| section.title.to_lowercase() == title.to_lowercase())
+            .find(|section| query_terms.iter().all(|term| section.terms.iter().any(|i| meta.terms[*i as usize] == *term)))
+            .map(|section| SectionLink { title: section.title.clone(), anchor: section.anchor.clone() })
+    }
+
+    fn rename_path(&mut self, from: &Path, to: &Path) -> usize {
+        let moved: Vec<(PathBuf, PathBuf)> = self.doc_ids.keys().chain(self.skipped.keys())
+            .filter_map(|p| renamed_path(p, from, to).map(|new| (p.clone(), new)))
+            .filter(|(old, new)| old != new)
+            .collect();
+
+        // Moved documents are taken out first, so one moving to the old path of another isn't dropped with it.
+        let taken: Vec<(&PathBuf, Option<DocId>, Option<SkippedDocument>)> = moved.iter()
+            .map(|(old, new)| (new, self.doc_ids.remove(old), self.skipped.remove(old)))
+            .collect();
+
+        for (new, id, skipped) in taken {
+            // A file that is replaced by the move is dropped.
+            self.remove_document(new.clone());
+
+            if let Some(id) = id {
+                if let Some(meta) = self.documents_meta.get_mut(&id) {
+                    meta.path = new.clone();
+                    meta.last_updated = FileStamp::of(new);
+                }
+                self.doc_ids.insert(new.clone(), id);
+            }else if let Some(mut skipped) = skipped {
+                skipped.last_updated = FileStamp::of(new);
+                self.skipped.insert(new.clone(), skipped);
+            }
+        }
+
+        moved.len()
     }
 
     // We currently store the index file as a json for debugging purposes but later op we 
     // will replace it with a bincoded file with varint enabled for a smaller index file size.
-    fn store_with_name(&mut self, index_path: &PathBuf) {
+    fn store_with_name(&mut self, index_path: &Path) {
         let index_file = File::create(index_path).unwrap();
-        self.path = Some(index_path.clone());
+        self.path = Some(index_path.to_path_buf());
         let bytes = bincode::serialize(&self).unwrap();
         let mut writer = BufWriter::new(index_file);
         writer.write_all(&bytes).unwrap();
@@ -140,11 +251,9 @@ impl Model for InvertedModel {
     }
 
     fn store(&mut self) {
-        match self.path.clone() {
-            Some(p) => self.store_with_name(&p),
-            None => ()
-
-        };
+        if let Some(p) = self.path.clone() {
+            self.store_with_name(&p);
+        }
 
     }
 
@@ -156,29 +265,47 @@ impl Model for InvertedModel {
         }
 
         let buf_reader = BufReader::new(index_file.unwrap());
-        let decoded: Option<InvertedModel> = bincode::deserialize_from(buf_reader).unwrap();
-        decoded
+        match bincode::deserialize_from::<_, InvertedModel>(buf_reader) {
+            Ok(mut decoded) => {
+                decoded.path = Some(index_path.to_path_buf());
+                tracing::debug!("Loaded index file {} from disk", index_path.display());
+                Some(decoded)
+            },
+            Err(e) => {
+                tracing::error!("Could not load index file {}, starting with an empty index: {e}", index_path.display());
+                None
+            }
+        }
     }
 
     fn search_simple(&self, query: &[char]) -> Vec<(PathBuf, f64)> {
-        let search_query: Vec<String> = Lexer::new_stemmed(query).collect();
-        let mut results: HashMap<PathBuf, f64> = HashMap::new();
-
-        for term in search_query {
-            if let Some(entry) = self.term_frequency.get(&term) {
-                for (file, tf) in entry {
-                    let tfidf = tf * (self.documents_meta.len() as f64 / entry.len() as f64).log10();
+        let mut results: HashMap<DocId, f64> = HashMap::new();
+
+        // Code is indexed unstemmed, so its documents are only matched by the unstemmed terms.
+        for code in [false, true] {
+            for term in query_terms(query, code) {
+                let Some(entry) = self.term_frequency.get(&term) else {
+                    continue;
+                };
+                for (id, tf) in entry {
+                    if self.documents_meta.get(id).is_none_or(|meta| meta.code != code) {
+                        continue;
+                    }
+                    let tfidf = tf * (self.count as f64 / entry.len() as f64).log10();
 
-                    if let Some(weight) = results.get_mut(file) {
+                    if let Some(weight) = results.get_mut(id) {
                         *weight += tfidf;
                     }else{
-                        results.insert(file.clone(), tfidf);
+                        results.insert(*id, tfidf);
                     }
                 }
             }
         }
 
-        let mut weighted: Vec<(PathBuf, f64)> = results.iter().map(|(path, weight)| (path.clone(), weight.clone())).filter(|(_, w)| w > &0.0 ).collect();
+        let mut weighted: Vec<(PathBuf, f64)> = results.iter()
+            .filter(|(_, w)| **w > 0.0 )
+            .filter_map(|(id, weight)| self.documents_meta.get(id).map(|meta| (meta.path.clone(), *weight)))
+            .collect();
 
         weighted.sort_by(|(_, rank1), (_, rank2)| {
             rank2.partial_cmp(rank1).unwrap()
@@ -202,7 +329,7 @@ impl Model for InvertedModel {
         unreachable!()
     }
 
-    fn assert_next_token_pos(&self, _doc: &super::base::Document, _pos: usize, _t: &String) -> bool {
+    fn assert_next_token_pos(&self, _doc: &super::base::Document, _pos: usize, _t: &str) -> bool {
         unreachable!()
     }
 
@@ -210,39 +337,115 @@ impl Model for InvertedModel {
         unreachable!()
     }
 
-    fn get_documents(&self) -> super::base::Documents {
-        todo!()
+    fn document_count(&self) -> usize {
+        self.count
+    }
+
+    fn remove_prefix(&mut self, path: &Path) -> usize {
+        let to_remove: Vec<DocId> = self.doc_ids.iter()
+            .filter(|(p, _)| is_within(p, path))
+            .map(|(_, id)| *id)
+            .collect();
+
+        for id in &to_remove {
+            self.remove_by_id(*id);
+        }
+
+        self.skipped.retain(|p, _| !is_within(p, path));
+        to_remove.len()
     }
 
     fn delete_removed_files(&mut self) {
-        let to_remove: Vec<PathBuf> = self.documents_meta.iter().filter_map(|(k, _)|{
-            if !k.exists() {
-                Some(k.to_path_buf())
+        let to_remove: Vec<DocId> = self.documents_meta.iter().filter_map(|(id, meta)|{
+            if !file_of(&meta.path).exists() {
+                Some(*id)
             }else{
                 None
             }
         }).collect();
 
-        for(_, list) in &mut self.term_frequency {
-            to_remove.iter().for_each(|file|{
-                list.remove(file);
-            });
-
-
+        for id in to_remove {
+            if let Some(meta) = self.remove_by_id(id) {
+                tracing::info!("Deleting removed file {} from index.", meta.path.display());
+            }
         }
 
+        self.skipped.retain(|k, _| file_of(k).exists());
+
 
         //unreachable!();
     }
 
-    fn add_document_batched(&mut self, batch: Vec<(PathBuf, Vec<char>)>, _dir: PathBuf) {
-        for (file, content) in batch {
-            self.add_document(file, &content);
+    fn add_document_batched(&mut self, batch: Vec<AnalyzedDocument>) {
+        for AnalyzedDocument { path, document } in batch {
+            // A changed document keeps its id, only its old postings are dropped.
+            let id = match self.doc_ids.get(&path).copied() {
+                Some(id) => {
+                    self.remove_by_id(id);
+                    id
+                },
+                None => {
+                    let id = self.next_doc_id;
+                    self.next_doc_id += 1;
+                    id
+                }
+            };
+
+            let count = document.count;
+            let mut terms = Vec::with_capacity(document.tf_stemmed.len());
+
+            for (term, freq) in document.tf_stemmed {
+                let tf = freq.count as f64 / count as f64;
+                if let Some(entry) = self.term_frequency.get_mut(&term) {
+                    entry.insert(id, tf);
+                }else{
+                    let mut inner_map: HashMap<DocId, TF> = HashMap::new();
+                    inner_map.insert(id, tf);
+                    self.term_frequency.insert(term.clone(), inner_map);
+                }
+                terms.push(term);
+            }
+
+            let term_ids: HashMap<&str, u32> = terms.iter().enumerate().map(|(i, t)| (t.as_str(), i as u32)).collect();
+            let sections = document.sections.into_iter().map(|section| InvertedSectionMeta {
+                terms: section.terms.iter().filter_map(|t| term_ids.get(t.as_str()).copied()).collect(),
+                title: section.title,
+                anchor: section.anchor
+            }).collect();
+
+            let doc_meta = InvertedModelDocumentMeta{
+                path: path.clone(),
+                terms,
+                last_updated: document.last_updated,
+                hash: document.hash,
+                simhash: document.simhash,
+                sections,
+                code: document.code,
+            };
+
+            if let Some(hash) = document.hash {
+                let ids = self.hashes.entry(hash).or_default();
+                if let Some(original) = ids.first().and_then(|i| self.documents_meta.get(i)) {
+                    tracing::info!("File {} has the same contents as {}", path.display(), original.path.display());
+                }
+                ids.push(id);
+            }
 
+            self.skipped.remove(&path);
+            self.doc_ids.insert(path, id);
+            self.documents_meta.insert(id, doc_meta);
+            self.count += 1;
         }
     }
 
     fn reset(&mut self) {
-        todo!()
+        self.term_frequency = HashMap::new();
+        self.documents_meta = HashMap::new();
+        self.doc_ids = HashMap::new();
+        self.next_doc_id = 0;
+        self.skipped = SkippedDocuments::new();
+        self.hashes = HashMap::new();
+        self.path = None;
+        self.count = 0;
     }
 }
@@ -1,16 +1,17 @@
-use std::{collections::HashMap, path::{PathBuf, Path}, fs::File, io::{BufWriter, BufReader}, time::{UNIX_EPOCH, SystemTime, SystemTimeError}, sync::{OnceLock, Arc, Mutex, RwLock}, fmt::Display};
+use std::{path::{PathBuf, Path}, fs::File, io::{BufWriter, BufReader}};
 use rayon::prelude::*;
-use rust_stemmers::{Algorithm, Stemmer};
 use serde::{Deserialize, Serialize};
 
 use crate::lexer::Lexer;
 
-use super::base::{Documents, DocumentFrequency, TermFrequency, TermInner, Model, Document, ReindexError, get_last_modified, calculate_tf, calculate_idf};
+use super::{ModelType, base::{AnalyzedDocument, SectionLink, best_section, query_terms, SkipReason, SkippedDocument, SkippedDocuments, Documents, DocumentFrequency, Model, Document, ReindexError, FileStamp, renamed_path, file_of, is_within, calculate_tf, calculate_idf}};
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct JsonModel {
     documents: Documents,
     df: DocumentFrequency,
     df_stemmed: DocumentFrequency,
+    #[serde(default)]
+    skipped: SkippedDocuments,
     #[serde(skip)]
     path: Option<PathBuf>
 
@@ -19,111 +20,172 @@ pub struct JsonModel {
 
 impl JsonModel {
     pub fn new() -> Self {
-        JsonModel { documents: Documents::new(), df: DocumentFrequency::new(), df_stemmed: DocumentFrequency::new(), path: None }
+        JsonModel { documents: Documents::new(), df: DocumentFrequency::new(), df_stemmed: DocumentFrequency::new(), skipped: SkippedDocuments::new(), path: None }
+    }
+
+    /// Number of distinct (unstemmed) terms in the index.
+    pub fn term_count(&self) -> usize {
+        self.df.len()
     }
 
     pub fn new_with_args(documents: Documents, df: DocumentFrequency, df_stemmed: DocumentFrequency, path: Option<PathBuf>) -> Self {
-        JsonModel { documents, df, df_stemmed, path }
+        JsonModel { documents, df, df_stemmed, skipped: SkippedDocuments::new(), path }
     }
 
 
 }
 
+/// Lowers the document frequency of a term, dropping the term once no document contains it.
+fn decrement_df(df: &mut DocumentFrequency, t: &str) {
+    if let Some(f) = df.get_mut(t) {
+        *f -= 1;
+        if *f == 0 {
+            df.remove(t);
+        }
+    }
+}
+
+impl Default for JsonModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Model for JsonModel {
 
     fn add_document(&mut self, path: PathBuf, content: &[char]) {
+        self.add_document_batched(vec![AnalyzedDocument::new(path, content, &ModelType::Json)]);
+    }
 
-        let metadata = std::fs::metadata(path.clone()).unwrap();
-
-        let lexer = Lexer::new(content);
-        
-        let mut term_frequency = TermFrequency::new();
-        let mut tf_stemmed = TermFrequency::new();
-        let stemmer = Stemmer::create(Algorithm::English);
-        let mut count: usize = 0;
+    #[allow(dead_code)]
+    fn remove_document(&mut self, path: PathBuf) {
+        self.skipped.remove(&path);
+        if let Some(d) = self.documents.remove(&path){
+            for t in d.tf.keys() {
+                decrement_df(&mut self.df, t);
+            }
+            for t in d.tf_stemmed.keys() {
+                decrement_df(&mut self.df_stemmed, t);
+            }
 
-        for (pos, token) in lexer.enumerate() {
-            let stemmed = stemmer.stem(token.as_str()).to_string();
+        }
+    }
 
-            if let Some(t) = term_frequency.get_mut(token.as_str()){
-                t.count += 1;
-                t.positions.push(pos);
 
-            }else{
-                term_frequency.insert(token.clone(), TermInner::new(pos));
-            }
+    fn add_skipped(&mut self, path: PathBuf, reason: SkipReason) {
+        let previous = self.skipped.get(&path).cloned();
+        self.remove_document(path.clone());
+        let skipped = SkippedDocument::new(&path, reason, previous.as_ref());
+        self.skipped.insert(path, skipped);
+    }
 
-            if let Some(t) = tf_stemmed.get_mut(&stemmed){
-                t.count += 1;
-                t.positions.push(pos);
+    fn get_skipped(&self) -> Vec<(PathBuf, SkipReason)> {
+        self.skipped.iter().map(|(p, s)| (p.clone(), s.reason.clone())).collect()
+    }
 
-            }else{
-                tf_stemmed.insert(stemmed.clone(), TermInner::new(pos));
-            }
-            count += 1;
+    fn retries_due(&self) -> Vec<PathBuf> {
+        self.skipped.iter().filter(|(_, s)| s.is_retry_due()).map(|(p, _)| p.clone()).collect()
+    }
 
+    fn needs_reindex(&self, path: &Path) -> Result<bool, ReindexError> {
+        if self.skipped.get(path).is_some_and(|s| s.is_retry_due()) {
+            return Ok(true);
         }
 
+        let last_updated = self.documents.get(path).map(|d| d.last_updated)
+            .or_else(|| self.skipped.get(path).map(|s| s.last_updated));
 
-        for t in term_frequency.keys() {
-            if let Some(f) = self.df.get_mut(t){
-                *f += 1;
-            }else{
-                self.df.insert(t.clone(), 1);
-            }
-        }
+        if let Some(last_updated) = last_updated {
+            Ok(FileStamp::read(path)? != last_updated)
+
+        }else{
+            Ok(true)
 
-        for t in tf_stemmed.keys() {
-            if let Some(f) = self.df_stemmed.get_mut(t){
-                *f += 1;
-            }else{
-                self.df_stemmed.insert(t.clone(), 1);
-            }
         }
 
-        self.documents.insert(path, Document { tf: term_frequency, tf_stemmed, count, last_updated: metadata.modified().unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize });
+    }
+
+    fn content_hash(&self, path: &Path) -> Option<u64> {
+        self.documents.get(path).and_then(|d| d.hash)
+    }
 
+    fn mark_unchanged(&mut self, path: &Path) {
+        if let Some(d) = self.documents.get_mut(path) {
+            d.last_updated = FileStamp::of(path);
+        }
     }
 
-    #[allow(dead_code)]
-    fn remove_document(&mut self, path: PathBuf) {
-        if let Some(d) = self.documents.remove(&path){
-            for t in d.tf.keys() {
-                if let Some(f) = self.df.get_mut(t) {
-                    *f -= 1;
+    fn duplicates_of(&self, path: &Path) -> Vec<PathBuf> {
+        let Some(hash) = self.content_hash(path) else {
+            return Vec::new();
+        };
 
-                }
-            }
-            for t in d.tf_stemmed.keys() {
-                if let Some(f) = self.df_stemmed.get_mut(t) {
-                    *f -= 1;
+        self.documents.iter()
+            .filter(|(p, d)| d.hash == Some(hash) && p.as_path() != path)
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
 
-                }
-            }
+    fn simhashes(&self) -> Vec<(PathBuf, u64, usize)> {
+        self.documents.iter().map(|(p, d)| (p.clone(), d.simhash, d.tf_stemmed.len())).collect()
+    }
 
-        }
+    fn paths_with_hash(&self, hash: u64) -> Vec<PathBuf> {
+        self.documents.iter()
+            .filter(|(_, d)| d.hash == Some(hash))
+            .map(|(p, _)| p.clone())
+            .collect()
     }
 
+    fn section_of(&self, path: &Path, query: &[char]) -> Option<SectionLink> {
+        let document = self.documents.get(path)?;
+        let query_terms = query_terms(query, document.code);
 
-    fn needs_reindex(&self, path: &PathBuf) -> Result<bool, ReindexError> {
-        if let Some(d) = self.documents.get(path) {
-            let last_modified = get_last_modified(path)?;
-            
-            let meta_modified = last_modified.duration_since(UNIX_EPOCH)?.as_secs() as usize;
-            Ok(meta_modified > d.last_updated)
+        best_section(&document.sections, &query_terms, |section, term| section.terms.binary_search_by(|t| t.as_str().cmp(term)).is_ok())
+            .map(|section| SectionLink { title: section.title.clone(), anchor: section.anchor.clone() })
+    }
 
+    fn section_matching(&self, path: &Path, title: &str, query: &[char]) -> Option<SectionLink> {
+        let document = self.documents.get(path)?;
+        let query_terms = query_terms(query, document.code);
 
-        }else{
-            Ok(true)
+        document.sections.iter()
+            .filter(|section| section.title.to_lowercase() == title.to_lowercase())
+            .find(|section| query_terms.iter().all(|term| section.terms.binary_search(term).is_ok()))
+            .map(|section| SectionLink { title: section.title.clone(), anchor: section.anchor.clone() })
+    }
 
+    fn rename_path(&mut self, from: &Path, to: &Path) -> usize {
+        let moved: Vec<(PathBuf, PathBuf)> = self.documents.keys().chain(self.skipped.keys())
+            .filter_map(|p| renamed_path(p, from, to).map(|new| (p.clone(), new)))
+            .filter(|(old, new)| old != new)
+            .collect();
+
+        // Moved documents are taken out first, so one moving to the old path of another isn't dropped with it.
+        let taken: Vec<(&PathBuf, Option<Document>, Option<SkippedDocument>)> = moved.iter()
+            .map(|(old, new)| (new, self.documents.remove(old), self.skipped.remove(old)))
+            .collect();
+
+        for (new, document, skipped) in taken {
+            // A file that is replaced by the move is dropped.
+            self.remove_document(new.clone());
+
+            if let Some(mut d) = document {
+                d.last_updated = FileStamp::of(new);
+                self.documents.insert(new.clone(), d);
+            }else if let Some(mut skipped) = skipped {
+                skipped.last_updated = FileStamp::of(new);
+                self.skipped.insert(new.clone(), skipped);
+            }
         }
 
+        moved.len()
     }
 
-    fn store_with_name(&mut self, index_path: &PathBuf){
+    fn store_with_name(&mut self, index_path: &Path){
 
         let index_file = File::create(index_path).unwrap();
-        self.path = Some(index_path.clone());
+        self.path = Some(index_path.to_path_buf());
         
         serde_json::to_writer(BufWriter::new(index_file), &self).unwrap();
     }
@@ -157,10 +219,12 @@ impl Model for JsonModel {
     }
 
     fn search_simple(&self, query: &[char]) -> Vec<(PathBuf, f64)> {
-        let qt: Vec<String> = Lexer::new_stemmed(query).collect();
+        let qt_prose = query_terms(query, false);
+        let qt_code = query_terms(query, true);
 
         let mut result: Vec<(PathBuf, f64)> = self.documents.iter().filter_map(|(p, d)|{
             let mut rank = 0_f64;
+            let qt = if d.code { &qt_code } else { &qt_prose };
             qt.iter().for_each(|t|{
 
                 let tf = calculate_tf(d, t, true);
@@ -296,7 +360,7 @@ impl Model for JsonModel {
 
     }
 
-    fn assert_next_token_pos(&self, doc: &Document, pos: usize, t: &String) -> bool {
+    fn assert_next_token_pos(&self, doc: &Document, pos: usize, t: &str) -> bool {
         if let Some(tf) = doc.tf.get(t) {
             tf.positions.contains(&pos)
         }else{
@@ -329,14 +393,27 @@ impl Model for JsonModel {
         }
     }
 
-    fn get_documents(&self) -> Documents {
-        self.documents.clone()
+    fn document_count(&self) -> usize {
+        self.documents.len()
+    }
 
+    fn remove_prefix(&mut self, path: &Path) -> usize {
+        let to_remove: Vec<PathBuf> = self.documents.keys()
+            .filter(|p| is_within(p, path))
+            .cloned()
+            .collect();
+
+        for p in &to_remove {
+            self.remove_document(p.clone());
+        }
+
+        self.skipped.retain(|p, _| !is_within(p, path));
+        to_remove.len()
     }
 
     fn delete_removed_files(&mut self) {
         let to_remove: Vec<PathBuf> = self.documents.par_iter().filter_map(|(k, _)|{
-            if !k.exists() {
+            if !file_of(k).exists() {
                 Some(k.to_path_buf())
             }else{
                 None
@@ -349,6 +426,8 @@ impl Model for JsonModel {
 
         });
 
+        self.skipped.retain(|k, _| file_of(k).exists());
+
     }
 
     fn reset(&mut self) {
@@ -356,10 +435,34 @@ impl Model for JsonModel {
         self.documents = Documents::new();
         self.df = DocumentFrequency::new();
         self.df_stemmed = DocumentFrequency::new();
+        self.skipped = SkippedDocuments::new();
         self.path = None;
     }
-    fn add_document_batched(&mut self, batch: Vec<(PathBuf, Vec<char>)>, dir: PathBuf){
-        todo!()
+    fn add_document_batched(&mut self, batch: Vec<AnalyzedDocument>){
+        for AnalyzedDocument { path, document } in batch {
+            if self.documents.contains_key(&path) {
+                self.remove_document(path.clone());
+            }
+
+            for t in document.tf.keys() {
+                if let Some(f) = self.df.get_mut(t){
+                    *f += 1;
+                }else{
+                    self.df.insert(t.clone(), 1);
+                }
+            }
+
+            for t in document.tf_stemmed.keys() {
+                if let Some(f) = self.df_stemmed.get_mut(t){
+                    *f += 1;
+                }else{
+                    self.df_stemmed.insert(t.clone(), 1);
+                }
+            }
+
+            self.skipped.remove(&path);
+            self.documents.insert(path, document);
+        }
 
     }
 }
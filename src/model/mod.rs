@@ -14,11 +14,6 @@ unsafe impl Sync for CorpusModel {}
 unsafe impl Send for CorpusModel {}
 
 
-enum ModelKind {
-    JsonModel(JsonModel),
-    InvertedModel(InvertedModel)
-}
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ModelType {
     Json,
@@ -32,7 +27,7 @@ pub fn path_to_index_name(path: &Path, kind: ModelType) -> PathBuf {
         eprintln!("Invalid directory name {}", path.display());
         std::process::exit(3);
     }
-    let name = path.file_name().unwrap().clone();
+    let name = path.file_name().unwrap();
     let mut index_name = name.to_str().unwrap().to_string();
 
     match kind {
@@ -54,16 +49,20 @@ pub struct CorpusModel{
 impl CorpusModel {
     pub fn new_json_model(index_path: &Path) -> Self {
 
-        let model = JsonModel::from_disk(&index_path).unwrap_or(JsonModel::new());
+        let model = JsonModel::from_disk(index_path).unwrap_or_default();
         CorpusModel { inner: Box::new(model), kind: ModelType::Json}
     }
 
     pub fn new_inverted_model(index_path: &Path) -> Self {
-        let model = InvertedModel::from_disk(&index_path).unwrap_or(InvertedModel::new());
+        let model = InvertedModel::from_disk(index_path).unwrap_or_default();
         CorpusModel { inner: Box::new(model), kind: ModelType::Inverted }
 
     }
 
+    pub fn kind(&self) -> ModelType {
+        self.kind.clone()
+    }
+
 }
 
 impl Model for CorpusModel {
@@ -75,11 +74,55 @@ impl Model for CorpusModel {
         self.inner.remove_document(path);
     }
 
-    fn needs_reindex(&self, path: &PathBuf) -> Result<bool, base::ReindexError> {
+    fn add_skipped(&mut self, path: PathBuf, reason: base::SkipReason) {
+        self.inner.add_skipped(path, reason);
+    }
+
+    fn get_skipped(&self) -> Vec<(PathBuf, base::SkipReason)> {
+        self.inner.get_skipped()
+    }
+
+    fn retries_due(&self) -> Vec<PathBuf> {
+        self.inner.retries_due()
+    }
+
+    fn needs_reindex(&self, path: &Path) -> Result<bool, base::ReindexError> {
         self.inner.needs_reindex(path)
     }
 
-    fn store_with_name(&mut self, index_path: &PathBuf) {
+    fn content_hash(&self, path: &Path) -> Option<u64> {
+        self.inner.content_hash(path)
+    }
+
+    fn mark_unchanged(&mut self, path: &Path) {
+        self.inner.mark_unchanged(path);
+    }
+
+    fn duplicates_of(&self, path: &Path) -> Vec<PathBuf> {
+        self.inner.duplicates_of(path)
+    }
+
+    fn simhashes(&self) -> Vec<(PathBuf, u64, usize)> {
+        self.inner.simhashes()
+    }
+
+    fn paths_with_hash(&self, hash: u64) -> Vec<PathBuf> {
+        self.inner.paths_with_hash(hash)
+    }
+
+    fn section_of(&self, path: &Path, query: &[char]) -> Option<base::SectionLink> {
+        self.inner.section_of(path, query)
+    }
+
+    fn section_matching(&self, path: &Path, title: &str, query: &[char]) -> Option<base::SectionLink> {
+        self.inner.section_matching(path, title, query)
+    }
+
+    fn rename_path(&mut self, from: &Path, to: &Path) -> usize {
+        self.inner.rename_path(from, to)
+    }
+
+    fn store_with_name(&mut self, index_path: &Path) {
         self.inner.store_with_name(index_path);
     }
 
@@ -87,7 +130,7 @@ impl Model for CorpusModel {
         self.inner.store();
     }
 
-    fn from_disk(index_path: &Path) -> Option<Self> where Self: Sized {
+    fn from_disk(_index_path: &Path) -> Option<Self> where Self: Sized {
         //self.inner.from_disk(index_path)
         unreachable!()
     }
@@ -114,30 +157,32 @@ impl Model for CorpusModel {
         }
     }
 
-    fn contains_tokens_sequential(&self, pos: usize, qt: Vec<String>, doc: &base::Document) -> bool {
+    fn contains_tokens_sequential(&self, _pos: usize, _qt: Vec<String>, _doc: &base::Document) -> bool {
         unreachable!();
     }
 
-    fn assert_next_token_pos(&self, doc: &base::Document, pos: usize, t: &String) -> bool {
+    fn assert_next_token_pos(&self, _doc: &base::Document, _pos: usize, _t: &str) -> bool {
         unreachable!();
-        todo!()
     }
 
-    fn docs_with_all_terms(&self, qt: &[String]) -> Option<Vec<PathBuf>> {
+    fn docs_with_all_terms(&self, _qt: &[String]) -> Option<Vec<PathBuf>> {
         unreachable!();
-        todo!()
     }
 
-    fn get_documents(&self) -> base::Documents {
-        unreachable!();
+    fn document_count(&self) -> usize {
+        self.inner.document_count()
+    }
+
+    fn remove_prefix(&mut self, path: &Path) -> usize {
+        self.inner.remove_prefix(path)
     }
 
     fn delete_removed_files(&mut self) {
         self.inner.delete_removed_files();
     }
 
-    fn add_document_batched(&mut self, batch: Vec<(PathBuf, Vec<char>)>, dir: PathBuf) {
-        self.inner.add_document_batched(batch, dir);
+    fn add_document_batched(&mut self, batch: Vec<base::AnalyzedDocument>) {
+        self.inner.add_document_batched(batch);
     }
 
     fn reset(&mut self) {
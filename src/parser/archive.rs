@@ -0,0 +1,547 @@
+use std::{cell::RefCell, fs::File, io::{self, BufReader, Cursor, Read}, ops::ControlFlow, path::{Path, PathBuf}};
+
+use flate2::read::GzDecoder;
+use tempfile::TempDir;
+use zip::ZipArchive;
+
+use crate::{config::get_config, model::base::{INNER_SEPARATOR, inner_path, split_inner_path}};
+
+use super::{ParsedText, error::ParserError, registry::{get_parsers, DocumentParser}, sniff::read_head};
+
+const TAR_BLOCK: usize = 512;
+
+/// Archives inside archives are unpacked up to this depth, the archive itself being the first level.
+const MAX_DEPTH: usize = 3;
+/// Members that are unpacked at most from an archive and the archives inside it.
+const MAX_MEMBERS: usize = 10_000;
+/// Bytes that are unpacked at most from an archive and the archives inside it.
+const MAX_UNPACKED_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+thread_local! {
+    /// What is left of the limits of the archive parsed on this thread. Nested archives are parsed
+    /// on the thread of the archive holding them, so they share its limits.
+    static LIMITS: RefCell<Limits> = const { RefCell::new(Limits { depth: 0, members: 0, bytes: 0 }) };
+}
+
+struct Limits {
+    depth: usize,
+    members: usize,
+    bytes: u64
+}
+
+/// The level of an archive being unpacked, left when it is dropped.
+struct Level;
+
+impl Level {
+    /// Enters an archive, with fresh limits for an archive that is not inside another one.
+    fn enter() -> Result<Level, ParserError> {
+        LIMITS.with_borrow_mut(|limits| {
+            if limits.depth == 0 {
+                *limits = Limits { depth: 0, members: MAX_MEMBERS, bytes: MAX_UNPACKED_BYTES };
+            }
+            if limits.depth >= MAX_DEPTH {
+                return Err(ParserError::limit_exceeded(format!("archive nested more than {MAX_DEPTH} levels deep")));
+            }
+            limits.depth += 1;
+            Ok(Level)
+        })
+    }
+}
+
+impl Drop for Level {
+    fn drop(&mut self) {
+        LIMITS.with_borrow_mut(|limits| limits.depth -= 1);
+    }
+}
+
+/// Parses every member of an archive that a parser supports, with the same detection as files
+/// in a directory. Members become documents named by their path in the archive, and the documents
+/// inside members, like those of a nested archive, are named by the path of the member followed by theirs.
+/// Members that can't be parsed become warnings. Unpacking stops with a warning at the limits of
+/// [`MAX_DEPTH`], [`MAX_MEMBERS`] and [`MAX_UNPACKED_BYTES`], which guard against archive bombs.
+pub fn get_archive(path: &Path, kind: &ArchiveParser) -> Result<ParsedText, ParserError> {
+    let _level = Level::enter()?;
+    let mut members = Members { archive: path, dir: tempfile::tempdir()?, parsed: ParsedText::default() };
+
+    match kind {
+        ArchiveParser::Zip => {
+            let mut zip = ZipArchive::new(File::open(path)?)?;
+            for i in 0..zip.len() {
+                let name = zip_member_name(&mut zip, i);
+                // Members with an unsupported compression method or encryption can't be read, unlike the others.
+                let mut member = match zip.by_index(i) {
+                    Ok(member) => member,
+                    Err(e) => {
+                        members.parsed.warn(ParserError::from(e).with_path(&inner_path(path, &name)));
+                        continue;
+                    }
+                };
+                if member.is_file() && members.add(&name, &mut member).is_break() {
+                    break;
+                }
+            }
+        },
+        ArchiveParser::Tar => read_tar(BufReader::new(File::open(path)?), |name, member| Ok(members.add(name, member)))?,
+        ArchiveParser::Gzip => {
+            let (is_tar, mut reader) = open_gzip(path)?;
+            if is_tar {
+                read_tar(reader, |name, member| Ok(members.add(name, member)))?;
+            }else{
+                // A compressed single file, like `notes.txt.gz`, holds the file without the extension.
+                let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+                let _ = members.add(&name, &mut reader);
+            }
+        }
+    }
+
+    Ok(members.parsed)
+}
+
+/// Collects the documents of the members of an archive, which are written to a temporary directory to be parsed.
+struct Members<'a> {
+    archive: &'a Path,
+    dir: TempDir,
+    parsed: ParsedText
+}
+
+impl Members<'_> {
+    /// Parses a member. Breaks when the limits of the archive are reached.
+    fn add(&mut self, name: &str, member: &mut dyn Read) -> ControlFlow<()> {
+        let name = name.trim_start_matches("./").trim_start_matches('/');
+        // Hidden files, like the resource forks of macOS in `__MACOSX/._name`, are ignored like in directories.
+        if name.split('/').any(|c| c.starts_with('.')) {
+            return ControlFlow::Continue(());
+        }
+        let Some(file_name) = Path::new(name).file_name() else {
+            return ControlFlow::Continue(());
+        };
+
+        let limit_reached = LIMITS.with_borrow_mut(|limits| {
+            let reached = limits.members == 0;
+            limits.members = limits.members.saturating_sub(1);
+            reached
+        });
+        if limit_reached {
+            let e = ParserError::limit_exceeded(format!("archive has more than {MAX_MEMBERS} members, the rest is skipped"));
+            self.parsed.warn(e.with_path(self.archive));
+            return ControlFlow::Break(());
+        }
+
+        let file = self.dir.path().join(file_name);
+        let result = self.parse(name, member, &file);
+        let _ = std::fs::remove_file(&file);
+
+        let inner = inner_path(self.archive, name);
+        match result {
+            Ok(Some(mut parsed)) => {
+                for mut warning in parsed.warnings.drain(..) {
+                    warning.path = Some(inner.clone());
+                    self.parsed.warn(warning);
+                }
+
+                if parsed.documents.is_empty() {
+                    self.parsed.documents.push((name.to_string(), parsed));
+                }else{
+                    for (document, parsed) in parsed.documents {
+                        self.parsed.documents.push((format!("{name}{INNER_SEPARATOR}{document}"), parsed));
+                    }
+                }
+            },
+            Ok(None) => (),
+            Err(mut e) => {
+                e.path = Some(inner);
+                self.parsed.warn(e);
+            }
+        }
+
+        if LIMITS.with_borrow(|limits| limits.bytes) == 0 {
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Writes the member to `file` and parses it. Yields `None` for members that are not supported,
+    /// too large or empty, and an error when the archive unpacks to more than [`MAX_UNPACKED_BYTES`].
+    fn parse(&self, name: &str, member: &mut dyn Read, file: &Path) -> Result<Option<ParsedText>, ParserError> {
+        let limit = get_config().max_file_size;
+        let bytes_left = LIMITS.with_borrow(|limits| limits.bytes);
+        let max = limit.unwrap_or(u64::MAX).min(bytes_left).saturating_add(1);
+        let size = io::copy(&mut member.take(max), &mut File::create(file)?)?;
+
+        LIMITS.with_borrow_mut(|limits| limits.bytes = limits.bytes.saturating_sub(size));
+        if size > bytes_left {
+            return Err(ParserError::limit_exceeded(format!("archive unpacks to more than {MAX_UNPACKED_BYTES} bytes, the rest is skipped")));
+        }
+        if let Some(limit) = limit.filter(|l| size > *l) {
+            tracing::info!("Skipping {name} in {}: size exceeds the limit of {limit} bytes", self.archive.display());
+            return Ok(None);
+        }
+
+        let Some(parser) = get_parsers().detect(file, &read_head(file)?) else {
+            return Ok(None);
+        };
+
+        let parsed = parser.parse(file)?;
+        if parsed.text.is_empty() && parsed.documents.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(parsed))
+    }
+}
+
+/// Name of a zip member, read without decompressing it so it is known for members that can't be opened.
+fn zip_member_name(zip: &mut ZipArchive<File>, i: usize) -> String {
+    zip.by_index_raw(i).map(|member| member.name().to_string()).unwrap_or_else(|_| format!("member {i}"))
+}
+
+/// Opens a gzip file and tells if it holds a tar archive by the header of its first block.
+fn open_gzip(path: &Path) -> Result<(bool, impl Read), ParserError> {
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+    let mut first = Vec::with_capacity(TAR_BLOCK);
+    (&mut decoder).take(TAR_BLOCK as u64).read_to_end(&mut first)?;
+
+    Ok((is_tar_header(&first), Cursor::new(first).chain(decoder.take(MAX_UNPACKED_BYTES))))
+}
+
+/// Whether the block is a tar header, checked by its checksum since old archives have no magic.
+pub fn is_tar_header(block: &[u8]) -> bool {
+    if block.len() < TAR_BLOCK || block[..TAR_BLOCK].iter().all(|b| *b == 0) {
+        return false;
+    }
+
+    let Some(checksum) = octal(&block[148..156]) else {
+        return false;
+    };
+    // The checksum is computed with its own field set to spaces.
+    let sum: u64 = block[..TAR_BLOCK].iter().enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 })
+        .sum();
+
+    sum == checksum
+}
+
+fn octal(field: &[u8]) -> Option<u64> {
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).ok()
+}
+
+/// Size field of a tar header, in octal or in the base-256 encoding GNU tar uses for large files.
+fn tar_size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return Some(field[1..].iter().fold(0u64, |size, b| (size << 8) | *b as u64));
+    }
+    octal(field)
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Calls `visit` with the path and contents of every regular file of a tar archive, until it breaks.
+/// Long paths of GNU and pax archives are supported, links and directories are skipped.
+fn read_tar(mut reader: impl Read, mut visit: impl FnMut(&str, &mut dyn Read) -> Result<ControlFlow<()>, ParserError>) -> Result<(), ParserError> {
+    let mut header = [0u8; TAR_BLOCK];
+    let mut long_name: Option<String> = None;
+
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into())
+        }
+        // The archive ends with blocks of zeros.
+        if header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        if !is_tar_header(&header) {
+            return Err(ParserError::corrupt("invalid tar header checksum"));
+        }
+
+        let size = tar_size(&header[124..136]).ok_or_else(|| ParserError::corrupt("invalid tar member size"))?;
+        let mut name = tar_string(&header[..100]);
+        if &header[257..262] == b"ustar" {
+            let prefix = tar_string(&header[345..500]);
+            if !prefix.is_empty() {
+                name = format!("{prefix}/{name}");
+            }
+        }
+
+        let mut data = (&mut reader).take(size);
+        match header[156] {
+            b'L' => {
+                let mut bytes = Vec::new();
+                data.read_to_end(&mut bytes)?;
+                long_name = Some(tar_string(&bytes));
+            },
+            b'x' => {
+                let mut bytes = Vec::new();
+                data.read_to_end(&mut bytes)?;
+                long_name = pax_path(&bytes).or(long_name);
+            },
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or(name);
+                if visit(&name, &mut data)?.is_break() {
+                    return Ok(());
+                }
+            },
+            _ => long_name = None
+        }
+
+        io::copy(&mut data, &mut io::sink())?;
+        let padding = (TAR_BLOCK as u64 - size % TAR_BLOCK as u64) % TAR_BLOCK as u64;
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+}
+
+/// The `path` record of a pax extended header, whose records look like `30 path=some/long/name\n`.
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records).lines()
+        .filter_map(|record| record.split_once(' '))
+        .find_map(|(_, record)| record.strip_prefix("path=").map(str::to_string))
+}
+
+/// Extracts a document inside an archive, like `archive.zip!/docs/report.pdf`, to a temporary file
+/// so it can be opened. Documents inside a file that is no archive, like a message of a mailbox,
+/// yield the file holding them. The temporary files are left for the application that opens them.
+pub fn extract_document(path: &Path) -> Result<PathBuf, ParserError> {
+    let Some((mut file, mut inner)) = split_inner_path(path) else {
+        return Ok(path.to_path_buf());
+    };
+    let dir = tempfile::Builder::new().prefix("knowledge_search").tempdir()?.keep();
+
+    for level in 0.. {
+        let Some(kind) = archive_kind(&file) else {
+            return Ok(file);
+        };
+        let (member, rest) = match inner.split_once(INNER_SEPARATOR) {
+            Some((member, rest)) => (member.to_string(), Some(rest.to_string())),
+            None => (inner.clone(), None)
+        };
+
+        let level_dir = dir.join(level.to_string());
+        std::fs::create_dir(&level_dir)?;
+        file = extract_member(&file, &kind, &member, &level_dir)?;
+
+        match rest {
+            Some(rest) => inner = rest,
+            None => break
+        }
+    }
+
+    Ok(file)
+}
+
+fn archive_kind(file: &Path) -> Option<ArchiveParser> {
+    let ext = file.extension()?.to_str()?.to_lowercase();
+    [ArchiveParser::Zip, ArchiveParser::Tar, ArchiveParser::Gzip].into_iter()
+        .find(|kind| kind.extensions().contains(&ext.as_str()))
+}
+
+/// Writes the member `name` of an archive to a file with the same name in `dir`.
+fn extract_member(archive: &Path, kind: &ArchiveParser, name: &str, dir: &Path) -> Result<PathBuf, ParserError> {
+    let file_name = Path::new(name).file_name().ok_or_else(|| ParserError::corrupt(format!("invalid member name {name}")))?;
+    let file = dir.join(file_name);
+    let is_member = |member: &str| member.trim_start_matches("./").trim_start_matches('/') == name;
+    let mut found = false;
+
+    match kind {
+        ArchiveParser::Zip => {
+            let mut zip = ZipArchive::new(File::open(archive)?)?;
+            for i in 0..zip.len() {
+                if !is_member(&zip_member_name(&mut zip, i)) {
+                    continue;
+                }
+                let mut member = zip.by_index(i).map_err(|e| ParserError::from(e).with_path(archive))?;
+                io::copy(&mut member, &mut File::create(&file)?)?;
+                found = true;
+                break;
+            }
+        },
+        ArchiveParser::Tar | ArchiveParser::Gzip => {
+            let mut extract = |member: &str, data: &mut dyn Read| -> Result<ControlFlow<()>, ParserError> {
+                if !is_member(member) {
+                    return Ok(ControlFlow::Continue(()));
+                }
+                io::copy(data, &mut File::create(&file)?)?;
+                found = true;
+                Ok(ControlFlow::Break(()))
+            };
+
+            match kind {
+                ArchiveParser::Tar => read_tar(BufReader::new(File::open(archive)?), &mut extract)?,
+                _ => {
+                    let (is_tar, mut reader) = open_gzip(archive)?;
+                    if is_tar {
+                        read_tar(reader, &mut extract)?;
+                    }else{
+                        let _ = extract(name, &mut reader)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if !found {
+        return Err(ParserError::corrupt(format!("no member {name} in the archive")).with_path(archive));
+    }
+    Ok(file)
+}
+
+/// Parser for archives, whose members are indexed like the files of a directory. Archives are only
+/// recognized by their extension, so formats based on zip like jar or apk are not unpacked.
+pub enum ArchiveParser {
+    Zip,
+    Tar,
+    /// Compressed tar archives, or a single compressed file.
+    Gzip
+}
+
+impl DocumentParser for ArchiveParser {
+    fn name(&self) -> &str {
+        match self {
+            ArchiveParser::Zip => "ZIP archive",
+            ArchiveParser::Tar => "Tar archive",
+            ArchiveParser::Gzip => "Gzip",
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        match self {
+            ArchiveParser::Zip => &["zip"],
+            ArchiveParser::Tar => &["tar"],
+            ArchiveParser::Gzip => &["gz", "tgz"],
+        }
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_archive(path, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tar header block with a valid checksum.
+    fn header(name: &str, size: u64, kind: u8) -> Vec<u8> {
+        let mut block = vec![0u8; TAR_BLOCK];
+        block[..name.len()].copy_from_slice(name.as_bytes());
+        block[124..135].copy_from_slice(format!("{size:011o}").as_bytes());
+        block[156] = kind;
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[148..156].fill(b' ');
+        let sum: u64 = block.iter().map(|b| *b as u64).sum();
+        block[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        block
+    }
+
+    fn member(tar: &mut Vec<u8>, name: &str, kind: u8, data: &[u8]) {
+        tar.extend(header(name, data.len() as u64, kind));
+        tar.extend(data);
+        tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    }
+
+    fn members(tar: &[u8]) -> Result<Vec<(String, String)>, ParserError> {
+        let mut found = Vec::new();
+        read_tar(tar, |name, data| {
+            let mut text = String::new();
+            data.read_to_string(&mut text)?;
+            found.push((name.to_string(), text));
+            Ok(ControlFlow::Continue(()))
+        })?;
+        Ok(found)
+    }
+
+    #[test]
+    fn checks_header_checksum() {
+        let mut block = header("notes.txt", 5, b'0');
+        assert!(is_tar_header(&block));
+
+        block[0] = b'm';
+        assert!(!is_tar_header(&block));
+        assert!(!is_tar_header(&[0u8; TAR_BLOCK]));
+        assert!(!is_tar_header(&block[..100]));
+    }
+
+    #[test]
+    fn reads_regular_files_and_skips_the_rest() {
+        let mut tar = Vec::new();
+        member(&mut tar, "docs/", b'5', b"");
+        member(&mut tar, "docs/a.txt", b'0', b"first");
+        member(&mut tar, "docs/link", b'2', b"");
+        member(&mut tar, "docs/b.txt", b'\0', &[b'x'; 600]);
+        tar.extend([0u8; 2 * TAR_BLOCK]);
+
+        let found = members(&tar).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], ("docs/a.txt".to_string(), "first".to_string()));
+        assert_eq!(found[1].0, "docs/b.txt");
+        assert_eq!(found[1].1.len(), 600);
+    }
+
+    #[test]
+    fn reads_long_names() {
+        let long = format!("{}/report.txt", "deep".repeat(40));
+        let mut tar = Vec::new();
+        member(&mut tar, "././@LongLink", b'L', format!("{long}\0").as_bytes());
+        member(&mut tar, "truncated", b'0', b"gnu");
+        member(&mut tar, "PaxHeaders/x", b'x', format!("{} path=pax/{long}\n", long.len() + 15).as_bytes());
+        member(&mut tar, "truncated", b'0', b"pax");
+
+        let found = members(&tar).unwrap();
+        assert_eq!(found[0], (long.clone(), "gnu".to_string()));
+        assert_eq!(found[1], (format!("pax/{long}"), "pax".to_string()));
+    }
+
+    #[test]
+    fn stops_when_visitor_breaks() {
+        let mut tar = Vec::new();
+        member(&mut tar, "a.txt", b'0', b"a");
+        member(&mut tar, "b.txt", b'0', b"b");
+
+        let mut visited = 0;
+        read_tar(tar.as_slice(), |_, _| {
+            visited += 1;
+            Ok(ControlFlow::Break(()))
+        }).unwrap();
+        assert_eq!(visited, 1);
+    }
+
+    #[test]
+    fn rejects_corrupt_header() {
+        let mut tar = Vec::new();
+        member(&mut tar, "a.txt", b'0', b"a");
+        tar[0] = b'b';
+        assert!(members(&tar).is_err());
+    }
+
+    #[test]
+    fn reads_base256_size() {
+        let mut field = [0u8; 12];
+        field[0] = 0x80;
+        field[7..].copy_from_slice(&[0x02, 0, 0, 0, 0]);
+        assert_eq!(tar_size(&field), Some(8 * 1024 * 1024 * 1024));
+        assert_eq!(tar_size(b"00000000012\0"), Some(10));
+        assert_eq!(tar_size(b"not octal\0\0\0"), None);
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let levels: Vec<Level> = (0..MAX_DEPTH).map(|_| Level::enter().unwrap()).collect();
+        let e = Level::enter().err().unwrap();
+        assert_eq!(e.kind(), "LimitExceeded");
+        assert!(!e.is_retryable());
+        drop(levels);
+        assert!(Level::enter().is_ok());
+    }
+
+    #[test]
+    fn recognizes_archives_by_extension() {
+        assert!(matches!(archive_kind(Path::new("a.ZIP")), Some(ArchiveParser::Zip)));
+        assert!(matches!(archive_kind(Path::new("a.tgz")), Some(ArchiveParser::Gzip)));
+        assert!(archive_kind(Path::new("a.jar")).is_none());
+        assert!(archive_kind(Path::new("archive")).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use crate::config::{get_config, CodeMode};
+
+use super::{ParsedText, error::ParserError, registry::DocumentParser, sniff::{is_binary, SNIFF_LEN}, txt::{decode_text, is_utf16}};
+
+/// Comment and string syntax of a programming language, which is detected by the extension of a file.
+pub struct Language {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub line_comments: &'static [&'static str],
+    /// Start and end of block comments. They are matched before line comments, e.g. `--[[` before `--` in Lua.
+    pub block_comments: &'static [(&'static str, &'static str)],
+    /// Quotes of string literals, a backslash escapes the next character inside them.
+    pub quotes: &'static [char]
+}
+
+const C_COMMENTS: &[(&str, &str)] = &[("/*", "*/")];
+
+pub const LANGUAGES: &[Language] = &[
+    Language { name: "Rust", extensions: &["rs"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "C", extensions: &["c", "h"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "C++", extensions: &["cpp", "cc", "cxx", "hpp", "hh", "hxx"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "C#", extensions: &["cs"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "Java", extensions: &["java"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "Kotlin", extensions: &["kt", "kts"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "Scala", extensions: &["scala"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "Swift", extensions: &["swift"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"'] },
+    Language { name: "Go", extensions: &["go"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"', '`'] },
+    Language { name: "JavaScript", extensions: &["js", "mjs", "cjs", "jsx"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"', '\'', '`'] },
+    Language { name: "TypeScript", extensions: &["ts", "mts", "cts", "tsx"], line_comments: &["//"], block_comments: C_COMMENTS, quotes: &['"', '\'', '`'] },
+    Language { name: "PHP", extensions: &["php"], line_comments: &["//", "#"], block_comments: C_COMMENTS, quotes: &['"', '\''] },
+    Language { name: "Python", extensions: &["py", "pyi"], line_comments: &["#"], block_comments: &[], quotes: &['"', '\''] },
+    Language { name: "Ruby", extensions: &["rb"], line_comments: &["#"], block_comments: &[("=begin", "=end")], quotes: &['"', '\''] },
+    Language { name: "Shell", extensions: &["sh", "bash", "zsh"], line_comments: &["#"], block_comments: &[], quotes: &['"', '\''] },
+    Language { name: "Lua", extensions: &["lua"], line_comments: &["--"], block_comments: &[("--[[", "]]")], quotes: &['"', '\''] },
+    Language { name: "SQL", extensions: &["sql"], line_comments: &["--"], block_comments: C_COMMENTS, quotes: &['\''] },
+    Language { name: "Haskell", extensions: &["hs"], line_comments: &["--"], block_comments: &[("{-", "-}")], quotes: &['"'] },
+];
+
+/// Reads a source file for [`CodeMode::Full`], or only its comments and string literals for [`CodeMode::Comments`].
+pub fn parse_code(path: &Path, language: &Language) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    // Extensions like `.ts` are shared with binary formats. UTF-16 has zero bytes too, but is text.
+    if !is_utf16(&bytes) && is_binary(&bytes[..bytes.len().min(SNIFF_LEN)]) {
+        return Ok(ParsedText::default());
+    }
+
+    let source: Vec<char> = decode_text(&bytes).chars().collect();
+    let text = match get_config().code_mode {
+        CodeMode::Comments => comments_and_strings(&source, language),
+        _ => source
+    };
+
+    Ok(ParsedText { text, code: true, ..Default::default() })
+}
+
+/// The text of the comments and string literals of `source`, separated by spaces.
+pub fn comments_and_strings(source: &[char], language: &Language) -> Vec<char> {
+    let mut text = Vec::new();
+    let mut i = 0;
+
+    while i < source.len() {
+        if let Some((start, end)) = language.block_comments.iter().find(|(start, _)| starts_with(&source[i..], start)) {
+            let from = i + start.chars().count();
+            let to = find(source, from, end).unwrap_or(source.len());
+            push_part(&mut text, &source[from..to]);
+            i = to + end.chars().count();
+        }else if let Some(marker) = language.line_comments.iter().find(|marker| starts_with(&source[i..], marker)) {
+            let from = i + marker.chars().count();
+            let to = source[from..].iter().position(|c| *c == '\n').map_or(source.len(), |n| from + n);
+            push_part(&mut text, &source[from..to]);
+            i = to;
+        }else if language.quotes.contains(&source[i]) {
+            let quote = source[i];
+            let mut to = i + 1;
+            while to < source.len() && source[to] != quote {
+                to += if source[to] == '\\' { 2 } else { 1 };
+            }
+            let to = to.min(source.len());
+            push_part(&mut text, &source[i + 1..to]);
+            i = to + 1;
+        }else{
+            i += 1;
+        }
+    }
+
+    text
+}
+
+fn starts_with(source: &[char], prefix: &str) -> bool {
+    let mut chars = source.iter();
+    prefix.chars().all(|c| chars.next() == Some(&c))
+}
+
+fn find(source: &[char], from: usize, needle: &str) -> Option<usize> {
+    (from..source.len()).find(|i| starts_with(&source[*i..], needle))
+}
+
+fn push_part(text: &mut Vec<char>, part: &[char]) {
+    if part.iter().all(|c| c.is_whitespace()) {
+        return;
+    }
+    if !text.is_empty() {
+        text.push(' ');
+    }
+    text.extend(part);
+}
+
+/// Parser for the source files of a language, registered for every language in [`LANGUAGES`]
+/// unless [`CodeMode::Off`] is set.
+pub struct CodeParser(pub &'static Language);
+
+impl DocumentParser for CodeParser {
+    fn name(&self) -> &str {
+        self.0.name
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.0.extensions
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &[]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        parse_code(path, self.0)
+    }
+}
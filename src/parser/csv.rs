@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, txt::decode_text};
+
+/// Delimiters a `.csv` file may use, the most frequent one in its first line is picked.
+const CSV_DELIMITERS: &[u8] = b",;\t|";
+
+/// Extracts the cells of a CSV or TSV table column by column. The first row is the header,
+/// every column becomes a section titled after its header, so a search can be limited to one column.
+pub fn parse_delimited(path: &Path, delimiter: Option<u8>) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    let mut parsed = delimited_to_text(&decode_text(&bytes), delimiter);
+    parsed.warnings = parsed.warnings.into_iter().map(|w| w.with_path(path)).collect();
+    Ok(parsed)
+}
+
+/// Extracts the cells of a table column by column, with the delimiter guessed from the first line when it is not given.
+pub fn delimited_to_text(source: &str, delimiter: Option<u8>) -> ParsedText {
+    let delimiter = delimiter.unwrap_or_else(|| guess_delimiter(source));
+
+    let mut parsed = ParsedText::default();
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(source.as_bytes());
+
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for record in reader.records() {
+        match record {
+            Ok(record) => rows.push(record.iter().map(|cell| cell.trim().to_string()).collect()),
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                parsed.warn(ParserError::corrupt(e.to_string()).within(format!("line {line}")));
+                break;
+            }
+        }
+    }
+
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    for column in 0..columns {
+        let cells: Vec<&str> = rows.iter()
+            .filter_map(|row| row.get(column))
+            .map(|cell| cell.as_str())
+            .filter(|cell| !cell.is_empty())
+            .collect();
+        if cells.is_empty() {
+            continue;
+        }
+
+        let title = match rows[0].get(column) {
+            Some(header) if !header.is_empty() => header.clone(),
+            _ => format!("Column {}", column + 1)
+        };
+
+        if !parsed.text.is_empty() {
+            parsed.text.push(' ');
+        }
+        parsed.sections.push(Section { title, anchor: None, level: None, start: parsed.text.len() });
+        parsed.text.extend(cells.join(" ").chars().map(|c| if c == '\n' || c == '\r' { ' ' } else { c }));
+    }
+
+    parsed
+}
+
+/// The delimiter that appears most often in the first line outside of quotes, a comma on a tie.
+fn guess_delimiter(source: &str) -> u8 {
+    let mut counts = [0; CSV_DELIMITERS.len()];
+    let mut quoted = false;
+
+    for byte in source.bytes() {
+        if byte == b'\n' && !quoted {
+            break;
+        }
+        if byte == b'"' {
+            quoted = !quoted;
+        }else if !quoted {
+            if let Some(i) = CSV_DELIMITERS.iter().position(|d| *d == byte) {
+                counts[i] += 1;
+            }
+        }
+    }
+
+    let best = (0..counts.len()).fold(0, |best, i| if counts[i] > counts[best] { i } else { best });
+    CSV_DELIMITERS[best]
+}
+
+pub enum CsvParser {
+    Csv,
+    Tsv
+}
+
+impl DocumentParser for CsvParser {
+    fn name(&self) -> &str {
+        match self {
+            CsvParser::Csv => "CSV",
+            CsvParser::Tsv => "TSV",
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        match self {
+            CsvParser::Csv => &["csv"],
+            CsvParser::Tsv => &["tsv", "tab"],
+        }
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        match self {
+            CsvParser::Csv => &["text/csv"],
+            CsvParser::Tsv => &["text/tab-separated-values"],
+        }
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        match self {
+            CsvParser::Csv => parse_delimited(path, None),
+            CsvParser::Tsv => parse_delimited(path, Some(b'\t')),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(source: &str, delimiter: Option<u8>) -> Vec<(String, String)> {
+        let parsed = delimited_to_text(source, delimiter);
+        let text: String = parsed.text.iter().collect();
+        let mut ends: Vec<usize> = parsed.sections.iter().skip(1).map(|s| s.start - 1).collect();
+        ends.push(parsed.text.len());
+        parsed.sections.iter().zip(ends)
+            .map(|(s, end)| (s.title.clone(), text.chars().skip(s.start).take(end - s.start).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn guesses_delimiter_from_first_line() {
+        assert_eq!(guess_delimiter("a,b,c\n1;2;3;4;5\n"), b',');
+        assert_eq!(guess_delimiter("a;b;c\n"), b';');
+        assert_eq!(guess_delimiter("\"x,y,z\"|b|c"), b'|');
+        assert_eq!(guess_delimiter("name"), b',');
+    }
+
+    #[test]
+    fn guesses_past_quoted_line_breaks() {
+        assert_eq!(guess_delimiter("\"multi\nline, with, commas\";b;c\n"), b';');
+    }
+
+    #[test]
+    fn reads_columns_titled_by_header() {
+        let found = columns("Name,Owner\nbudget,alice\nroadmap,bob\n", None);
+        assert_eq!(found, [
+            ("Name".to_string(), "Name budget roadmap".to_string()),
+            ("Owner".to_string(), "Owner alice bob".to_string())
+        ]);
+    }
+
+    #[test]
+    fn reads_quoted_cells() {
+        let found = columns("Note;Id\n\"semi;colon\";1\n\"two\nlines, \"\"quoted\"\"\";2\n", None);
+        assert_eq!(found[0].1, "Note semi;colon two lines, \"quoted\"");
+        assert_eq!(found[1].1, "Id 1 2");
+    }
+
+    #[test]
+    fn names_columns_without_header_and_skips_empty_ones() {
+        let found = columns("a\t\t\n1\t\t\n2\t\tlate\n", Some(b'\t'));
+        let titles: Vec<&str> = found.iter().map(|(title, _)| title.as_str()).collect();
+        assert_eq!(titles, ["a", "Column 3"]);
+        assert_eq!(found[1].1, "late");
+    }
+}
@@ -1,46 +1,37 @@
-use std::{path::Path, fs::File};
+use std::{collections::HashMap, path::Path, fs::File, io::{Read, Seek}};
 
-use xml::EventReader;
+use xml::{EventReader, reader::XmlEvent};
 use zip::{ZipArchive, result::ZipError, read::ZipFile};
 
-use super::error::ParserError;
+use super::{ParsedText, error::ParserError, registry::DocumentParser};
 
 
 
-pub fn get_docx_text_manual(path: &Path) -> Result<Vec<char>, ParserError> {
+/// Extracts the text of the body, notes, comments, headers and footers. Only the body is required,
+/// the other parts become warnings when they can't be read.
+pub fn get_docx_text_manual(path: &Path) -> Result<ParsedText, ParserError> {
     let mut result = String::new();
+    let mut parsed = ParsedText::default();
     let mut zip = ZipArchive::new(File::open(path)?)?;
 
-    let document = zip_read("word/document.xml".to_string(), &mut zip)?;
-    let footnotes = zip_option_read("word/footnotes.xml".to_string(), &mut zip)?;
-    let endnotes = zip_option_read("word/endnotes.xml".to_string(), &mut zip)?;
-    let comments = zip_option_read("word/comments.xml".to_string(), &mut zip)?;
-
-    let headers = zip_option_read_multiple("word/header".to_string(), &mut zip)?;
-    let footers = zip_option_read_multiple("word/footer".to_string(), &mut zip)?;
-
+    let document = zip_read("word/document.xml".to_string(), &mut zip, "w:t")?;
     result.push_str(document.as_str());
-    if let Some(txt) = footnotes {
-        result.push_str(txt.as_str());
-
-    }
-
-    if let Some(txt) = endnotes {
-        result.push_str(txt.as_str());
-
-    }
-    if let Some(txt) = comments {
-        result.push_str(txt.as_str());
-
-    }
-
-    for (_, txt) in headers {
-        result.push_str(txt.as_str());
 
+    for part in ["word/footnotes.xml", "word/endnotes.xml", "word/comments.xml"] {
+        match zip_option_read(part.to_string(), &mut zip, "w:t") {
+            Ok(Some(txt)) => result.push_str(txt.as_str()),
+            Ok(None) => (),
+            Err(e) => parsed.warn(e.with_path(path))
+        }
     }
-    for (_, txt) in footers {
-        result.push_str(txt.as_str());
 
+    for prefix in ["word/header", "word/footer"] {
+        for part in zip_option_read_multiple(prefix.to_string(), &mut zip, "w:t") {
+            match part {
+                Ok((_, txt)) => result.push_str(txt.as_str()),
+                Err(e) => parsed.warn(e.with_path(path))
+            }
+        }
     }
 
 
@@ -54,11 +45,14 @@ pub fn get_docx_text_manual(path: &Path) -> Result<Vec<char>, ParserError> {
     */
 
 
-    Ok(result.chars().collect())
+    parsed.text = result.chars().collect();
+    Ok(parsed)
 }
 
-fn zip_parse_text(parser: EventReader<ZipFile>) -> Result<String, ParserError> {
+/// Collects the characters of the `tag` elements (like `w:t`), skipping alternative content.
+fn zip_parse_text(parser: EventReader<ZipFile>, tag: &str) -> Result<String, ParserError> {
     let mut result = String::new();
+    let tag = format!("}}{tag}");
 
     let mut in_text = false;
     let mut in_choice = false;
@@ -70,7 +64,7 @@ fn zip_parse_text(parser: EventReader<ZipFile>) -> Result<String, ParserError> {
                 if name.to_string().ends_with("}mc:Choice") {
                     in_choice = true;
                 }
-                if name.to_string().ends_with("}w:t") {
+                if name.to_string().ends_with(&tag) {
                     in_text = true;
 
                 }
@@ -79,7 +73,7 @@ fn zip_parse_text(parser: EventReader<ZipFile>) -> Result<String, ParserError> {
                 if name.to_string().ends_with("}mc:Choice") {
                     in_choice = false;
                 }
-                if name.to_string().ends_with("}w:t") {
+                if name.to_string().ends_with(&tag) {
                     in_text = false;
 
                 }
@@ -95,7 +89,7 @@ fn zip_parse_text(parser: EventReader<ZipFile>) -> Result<String, ParserError> {
             Ok(_) => (),
             //eprintln!("element of type {elem:?} is being ignored"),
             Err(e) =>{
-                return Err(ParserError::XmlError(e))
+                return Err(ParserError::from(e))
             },
         }
 
@@ -103,43 +97,111 @@ fn zip_parse_text(parser: EventReader<ZipFile>) -> Result<String, ParserError> {
     Ok(result)
 }
 
-fn zip_read(file: String, zip: &mut ZipArchive<File>) -> Result<String, ParserError> {
+pub(super) fn zip_read<R: Read + Seek>(name: String, zip: &mut ZipArchive<R>, tag: &str) -> Result<String, ParserError> {
 
-    let file = zip.by_name(file.as_str())?;
+    let file = zip.by_name(name.as_str()).map_err(|e| ParserError::from(e).within(name.as_str()))?;
     let parser = EventReader::new(file);
-    zip_parse_text(parser)
+    zip_parse_text(parser, tag).map_err(|e| e.within(name))
 }
 
-fn zip_option_read(file: String, zip: &mut ZipArchive<File>) -> Result<Option<String>, ParserError>{
-    match zip.by_name(file.as_str()) {
+pub(super) fn zip_option_read<R: Read + Seek>(name: String, zip: &mut ZipArchive<R>, tag: &str) -> Result<Option<String>, ParserError>{
+    match zip.by_name(name.as_str()) {
         Err(ZipError::FileNotFound) => Ok(None),
-        Err(_) => Ok(None),
+        Err(e) => Err(ParserError::from(e).within(name)),
         Ok(file) => {
             let parser = EventReader::new(file);
 
             //file.read_to_string(&mut buffer).ok()?;
-            let temp = zip_parse_text(parser)?;
+            let temp = zip_parse_text(parser, tag).map_err(|e| e.within(name))?;
             Ok(Some(temp))
         }
     }
 
 }
 
-fn zip_option_read_multiple(file: String, zip: &mut ZipArchive<File>) -> Result<Vec<(String, String)>, ParserError> {
+pub(super) fn zip_option_read_multiple<R: Read + Seek>(file: String, zip: &mut ZipArchive<R>, tag: &str) -> Vec<Result<(String, String), ParserError>> {
         let names: Vec<String> = zip.file_names().map(|x| x.to_string()).collect();
-        let name_and_value: Result<Vec<(String, String)>, ParserError> = names
+        names
             .iter()
             .filter(|n| n.contains(file.as_str()))
-            .filter_map(|f| {
-                zip.by_name(f).ok().map(|file| {
-                
-                    let parser = EventReader::new(file);
-                    let temp = zip_parse_text(parser)?;
-
-                    Ok((f.to_string(), temp))
-                })
-            })
-            .collect();
-        name_and_value
+            .map(|f| zip_read(f.to_string(), zip, tag).map(|temp| (f.to_string(), temp)))
+            .collect()
+
+}
 
+/// A relationship of an Office Open XML part, with the target resolved to a path in the zip.
+pub(super) struct Relationship {
+    pub kind: String,
+    pub target: String
+}
+
+/// Relationships of the part `name` by id, read from the `_rels/<part>.rels` next to it.
+/// A part without relationships has none, external targets like hyperlinks are left out.
+pub(super) fn zip_relationships<R: Read + Seek>(name: &str, zip: &mut ZipArchive<R>) -> Result<HashMap<String, Relationship>, ParserError> {
+    let (dir, file) = name.rsplit_once('/').unwrap_or(("", name));
+    let rels_name = if dir.is_empty() { format!("_rels/{file}.rels") } else { format!("{dir}/_rels/{file}.rels") };
+    let mut relationships = HashMap::new();
+
+    let rels = match zip.by_name(&rels_name) {
+        Ok(rels) => rels,
+        Err(ZipError::FileNotFound) => return Ok(relationships),
+        Err(e) => return Err(ParserError::from(e).within(rels_name))
+    };
+
+    for event in EventReader::new(rels) {
+        match event.map_err(|e| ParserError::from(e).within(rels_name.as_str()))? {
+            XmlEvent::StartElement { name, attributes, .. } if name.local_name == "Relationship" => {
+                let attribute = |n: &str| attributes.iter().find(|a| a.name.local_name == n).map(|a| a.value.clone());
+                if attribute("TargetMode").as_deref() == Some("External") {
+                    continue;
+                }
+                if let (Some(id), Some(kind), Some(target)) = (attribute("Id"), attribute("Type"), attribute("Target")) {
+                    relationships.insert(id, Relationship { kind, target: resolve_target(dir, &target) });
+                }
+            },
+            _ => ()
+        }
+    }
+
+    Ok(relationships)
+}
+
+/// Resolves the target of a relationship, which is relative to the directory of its part unless it starts with `/`.
+pub(super) fn resolve_target(dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => dir.split('/').filter(|p| !p.is_empty()).collect()
+    };
+
+    for part in target.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            },
+            _ => parts.push(part)
+        }
+    }
+
+    parts.join("/")
+}
+
+pub struct DocxParser;
+
+impl DocumentParser for DocxParser {
+    fn name(&self) -> &str {
+        "DOCX"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["docx"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_docx_text_manual(path)
+    }
 }
@@ -0,0 +1,532 @@
+use std::{collections::HashMap, path::Path};
+
+use encoding_rs::{Encoding, UTF_8};
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, html::html_to_text, sniff::text_starts_with};
+
+/// Header fields that are indexed, each as a section titled after the field.
+const FIELDS: [&str; 5] = ["From", "To", "Cc", "Subject", "Date"];
+
+/// Depth of nested multipart entities and attached messages that is still read.
+const MAX_DEPTH: usize = 16;
+
+type Headers = Vec<(String, String)>;
+
+pub fn parse_eml(path: &Path) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    let mut parsed = message_to_text(&bytes);
+    parsed.warnings = parsed.warnings.into_iter().map(|w| w.with_path(path)).collect();
+    Ok(parsed)
+}
+
+/// Splits a mailbox into its messages, which become separate documents named by their
+/// number in the mailbox, starting at 1.
+pub fn parse_mbox(path: &Path) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    let mut parsed = ParsedText::default();
+    if bytes.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(parsed);
+    }
+
+    let messages = split_mbox(&bytes);
+    if messages.is_empty() {
+        return Err(ParserError::corrupt("mailbox does not start with a From line"));
+    }
+
+    for (i, message) in messages.iter().enumerate() {
+        let name = (i + 1).to_string();
+        let mut message = message_to_text(message);
+        for warning in std::mem::take(&mut message.warnings) {
+            parsed.warn(warning.within(format!("message {name}")).with_path(path));
+        }
+        parsed.documents.push((name, message));
+    }
+
+    Ok(parsed)
+}
+
+/// Splits a mailbox at the `From ` lines that start its messages, and removes the `>` that
+/// quotes `From ` at the start of lines inside the messages.
+fn split_mbox(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages: Vec<Vec<u8>> = Vec::new();
+    let mut after_blank = true;
+
+    for line in bytes.split_inclusive(|b| *b == b'\n') {
+        if after_blank && line.starts_with(b"From ") {
+            messages.push(Vec::new());
+        }else if let Some(message) = messages.last_mut() {
+            let quotes = line.iter().take_while(|b| **b == b'>').count();
+            if quotes > 0 && line[quotes..].starts_with(b"From ") {
+                message.extend_from_slice(&line[1..]);
+            }else{
+                message.extend_from_slice(line);
+            }
+        }
+        after_blank = line.iter().all(|b| b.is_ascii_whitespace());
+    }
+
+    messages
+}
+
+/// Extracts the indexed header fields and the text of the body of a message. Every field and
+/// the body become a section, so results point at the part of the message that matched.
+pub fn message_to_text(raw: &[u8]) -> ParsedText {
+    let (headers, body) = split_headers(raw);
+    let mut parsed = ParsedText::default();
+
+    for field in FIELDS {
+        let value = field_value(&headers, field);
+        if value.trim().is_empty() {
+            continue;
+        }
+        let start = next_start(&parsed);
+        parsed.sections.push(Section { title: field.to_string(), anchor: None, level: None, start });
+        push_text(&mut parsed, &value);
+    }
+
+    let start = next_start(&parsed);
+    let len = parsed.text.len();
+    parsed.sections.push(Section { title: "Body".to_string(), anchor: None, level: None, start });
+    push_entity(&headers, body, 0, &mut parsed);
+    if parsed.text.len() == len {
+        parsed.sections.pop();
+    }
+
+    parsed
+}
+
+/// Parses the header fields of a message or MIME entity, joining folded lines. Returns them together
+/// with the body after the blank line that ends them.
+fn split_headers(raw: &[u8]) -> (Headers, &[u8]) {
+    let mut headers: Headers = Vec::new();
+    let mut pos = 0;
+
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        pos += line.len();
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line.is_empty() {
+            return (headers, &raw[pos..]);
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        }else if let Some((name, value)) = line.split_once(':') {
+            // Skips the `From ` line of a message saved from a mailbox.
+            if !name.contains(char::is_whitespace) {
+                headers.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+    }
+
+    (headers, &raw[raw.len()..])
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+/// The decoded values of every header field called `name`.
+fn field_value(headers: &[(String, String)], name: &str) -> String {
+    headers.iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| decode_encoded_words(v))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The lowercase value of a `Content-Type` or `Content-Disposition` field and its parameters.
+fn parse_parameters(value: &str) -> (String, HashMap<String, String>) {
+    let mut parts = value.split(';');
+    let value = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let parameters = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().trim_matches('"').to_string()))
+        .collect();
+
+    (value, parameters)
+}
+
+fn content_type(headers: &[(String, String)]) -> (String, HashMap<String, String>) {
+    match header(headers, "Content-Type") {
+        Some(value) => parse_parameters(value),
+        None => ("text/plain".to_string(), HashMap::new())
+    }
+}
+
+/// Appends the text of a MIME entity. Of alternative parts only the plain text is read when there is
+/// one, html is rendered as text. Attachments and parts that are not text are left out.
+fn push_entity(headers: &[(String, String)], body: &[u8], depth: usize, parsed: &mut ParsedText) {
+    if header(headers, "Content-Disposition").is_some_and(|d| parse_parameters(d).0 == "attachment") {
+        return;
+    }
+
+    let (mime, parameters) = content_type(headers);
+    let nested = mime.starts_with("multipart/") || mime == "message/rfc822";
+    if nested && depth >= MAX_DEPTH {
+        parsed.warn(ParserError::corrupt("MIME parts are nested too deeply"));
+        return;
+    }
+
+    if mime.starts_with("multipart/") {
+        let Some(boundary) = parameters.get("boundary") else {
+            parsed.warn(ParserError::corrupt(format!("{mime} part without a boundary")));
+            return;
+        };
+
+        let parts: Vec<(Headers, &[u8])> = split_multipart(body, boundary).into_iter().map(split_headers).collect();
+        if mime == "multipart/alternative" {
+            // The alternatives hold the same text, so only one of them is read.
+            let part = parts.iter().find(|(h, _)| content_type(h).0 == "text/plain").or(parts.last());
+            if let Some((headers, body)) = part {
+                push_entity(headers, body, depth + 1, parsed);
+            }
+        }else{
+            for (headers, body) in &parts {
+                push_entity(headers, body, depth + 1, parsed);
+            }
+        }
+        return;
+    }
+
+    let body = decode_transfer_encoding(headers, body);
+    match mime.as_str() {
+        "message/rfc822" => {
+            let (headers, body) = split_headers(&body);
+            for field in FIELDS {
+                push_text(parsed, &field_value(&headers, field));
+            }
+            push_entity(&headers, body, depth + 1, parsed);
+        },
+        "text/plain" | "text/html" => {
+            let charset = parameters.get("charset").map(String::as_str).unwrap_or("utf-8");
+            let encoding = Encoding::for_label(charset.trim().as_bytes()).unwrap_or_else(|| {
+                parsed.warn(ParserError::unsupported(format!("charset {charset}, read as UTF-8")));
+                UTF_8
+            });
+            let (text, _, _) = encoding.decode(&body);
+
+            if mime == "text/html" {
+                push_text(parsed, &html_to_text(text.as_bytes()).into_iter().collect::<String>());
+            }else{
+                push_text(parsed, &text);
+            }
+        },
+        _ => ()
+    }
+}
+
+/// The parts of a multipart body between its boundary lines.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut pos = 0;
+
+    for line in body.split_inclusive(|b| *b == b'\n') {
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes()) {
+            let is_last = rest.starts_with(b"--");
+            if is_last || rest.iter().all(|b| b.is_ascii_whitespace()) {
+                if let Some(start) = start {
+                    parts.push(&body[start..pos]);
+                }
+                if is_last {
+                    return parts;
+                }
+                start = Some(pos + line.len());
+            }
+        }
+        pos += line.len();
+    }
+
+    // A body that is cut off still yields its last part.
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+fn decode_transfer_encoding(headers: &[(String, String)], body: &[u8]) -> Vec<u8> {
+    match header(headers, "Content-Transfer-Encoding").map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => decode_base64(body),
+        Some("quoted-printable") => decode_quoted_printable(body),
+        _ => body.to_vec()
+    }
+}
+
+/// Decodes base64, skipping line breaks and any other characters outside of the alphabet.
+fn decode_base64(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for b in input {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => continue
+        };
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    out
+}
+
+/// Decodes quoted-printable, where `=` followed by two hex digits is a byte and `=` at the end of a line joins it with the next one.
+fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let hex = |b: Option<&u8>| b.and_then(|b| (*b as char).to_digit(16)).map(|d| d as u8);
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+
+    while i < input.len() {
+        if input[i] != b'=' {
+            out.push(input[i]);
+            i += 1;
+            continue;
+        }
+
+        let rest = &input[i + 1..];
+        if rest.starts_with(b"\r\n") {
+            i += 3;
+        }else if rest.starts_with(b"\n") {
+            i += 2;
+        }else if let (Some(high), Some(low)) = (hex(rest.first()), hex(rest.get(1))) {
+            out.push((high << 4) | low);
+            i += 3;
+        }else{
+            out.push(b'=');
+            i += 1;
+        }
+    }
+
+    out
+}
+
+/// Decodes the encoded words of RFC 2047 like `=?utf-8?Q?caf=C3=A9?=` in a header field.
+/// Whitespace between two encoded words is dropped.
+fn decode_encoded_words(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match encoded_word(candidate) {
+            Some((text, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&text);
+                rest = &candidate[len..];
+                after_word = true;
+            },
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// The text of the encoded word at the start of `word` and its length.
+fn encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => decode_base64(text.as_bytes()),
+        "Q" | "q" => decode_quoted_printable(&text.bytes().map(|b| if b == b'_' { b' ' } else { b }).collect::<Vec<u8>>()),
+        _ => return None
+    };
+
+    // The charset can carry a language like `utf-8*en`.
+    let charset = charset.split('*').next().unwrap_or_default();
+    let encoding = Encoding::for_label(charset.as_bytes()).unwrap_or(UTF_8);
+    let len = word.len() - inner.len() + end + 2;
+    Some((encoding.decode(&bytes).0.into_owned(), len))
+}
+
+/// Offset the text pushed next starts at.
+fn next_start(parsed: &ParsedText) -> usize {
+    if parsed.text.is_empty() { 0 } else { parsed.text.len() + 1 }
+}
+
+fn push_text(parsed: &mut ParsedText, text: &str) {
+    if text.trim().is_empty() {
+        return;
+    }
+
+    if !parsed.text.is_empty() {
+        parsed.text.push(' ');
+    }
+    parsed.text.extend(text.trim().chars().filter(|c| *c != '\r').map(|c| if c == '\n' { ' ' } else { c }));
+}
+
+/// Parser for single messages and for mailboxes, whose messages are indexed as separate documents.
+pub enum EmailParser {
+    Message,
+    Mailbox
+}
+
+impl DocumentParser for EmailParser {
+    fn name(&self) -> &str {
+        match self {
+            EmailParser::Message => "Email",
+            EmailParser::Mailbox => "Mailbox",
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        match self {
+            EmailParser::Message => &["eml"],
+            EmailParser::Mailbox => &["mbox"],
+        }
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        match self {
+            EmailParser::Message => &["message/rfc822"],
+            EmailParser::Mailbox => &["application/mbox"],
+        }
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        match self {
+            EmailParser::Message => ["Return-Path:", "Received:", "Delivered-To:", "Message-ID:", "MIME-Version:"]
+                .iter().any(|field| text_starts_with(head, field)),
+            // Mail clients store mailboxes without an extension, like `Inbox`.
+            EmailParser::Mailbox => head.starts_with(b"From "),
+        }
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        match self {
+            EmailParser::Message => parse_eml(path),
+            EmailParser::Mailbox => parse_mbox(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(parsed: &ParsedText) -> String {
+        parsed.text.iter().collect()
+    }
+
+    fn titles(parsed: &ParsedText) -> Vec<&str> {
+        parsed.sections.iter().map(|s| s.title.as_str()).collect()
+    }
+
+    #[test]
+    fn decodes_base64_across_lines() {
+        assert_eq!(decode_base64(b"aGVsbG8g\r\nd29ybGQ="), b"hello world");
+        assert_eq!(decode_base64(b"YQ=="), b"a");
+        assert_eq!(decode_base64(b""), b"");
+    }
+
+    #[test]
+    fn decodes_quoted_printable() {
+        assert_eq!(decode_quoted_printable(b"caf=C3=A9"), "café".as_bytes());
+        assert_eq!(decode_quoted_printable(b"soft=\r\nbreak and=\nagain"), b"softbreak andagain");
+        assert_eq!(decode_quoted_printable(b"1+1=2 =ZZ"), b"1+1=2 =ZZ");
+        assert_eq!(decode_quoted_printable(b"end="), b"end=");
+    }
+
+    #[test]
+    fn decodes_encoded_words() {
+        assert_eq!(decode_encoded_words("=?utf-8?Q?caf=C3=A9_au_lait?="), "café au lait");
+        assert_eq!(decode_encoded_words("=?UTF-8?B?aGVsbG8=?= =?utf-8?q?_world?="), "hello world");
+        assert_eq!(decode_encoded_words("Re: =?iso-8859-1?q?r=E9sum=E9?= attached"), "Re: résumé attached");
+        assert_eq!(decode_encoded_words("=?broken word?="), "=?broken word?=");
+    }
+
+    #[test]
+    fn splits_mbox_at_from_lines_after_blank_lines() {
+        let mbox = b"From alice Mon Jan 1 00:00:00 2024\nSubject: one\n\nbody\nFrom here on\n>From the quoted line\n\nFrom bob Tue Jan 2 00:00:00 2024\nSubject: two\n\nbody\n";
+        let messages = split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], b"Subject: one\n\nbody\nFrom here on\nFrom the quoted line\n\n");
+        assert_eq!(messages[1], b"Subject: two\n\nbody\n");
+    }
+
+    #[test]
+    fn unquotes_only_one_level_of_from() {
+        let messages = split_mbox(b"From a\n\n>>From deeper\n");
+        assert_eq!(messages, [b"\n>From deeper\n".to_vec()]);
+    }
+
+    #[test]
+    fn reads_fields_as_sections_and_joins_folded_lines() {
+        let parsed = message_to_text(b"From: alice@example.com\r\nSubject: quarterly\r\n report\r\nX-Other: skipped\r\n\r\nHello\r\n");
+        assert_eq!(titles(&parsed), ["From", "Subject", "Body"]);
+        assert_eq!(text(&parsed), "alice@example.com quarterly report Hello");
+        assert!(parsed.sections.iter().all(|s| s.level.is_none()));
+    }
+
+    #[test]
+    fn prefers_plain_text_of_alternatives() {
+        let message = b"Content-Type: multipart/alternative; boundary=\"b1\"\n\n\
+            --b1\nContent-Type: text/html\n\n<p>html version</p>\n\
+            --b1\nContent-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: quoted-printable\n\nplain caf=C3=A9\n\
+            --b1--\n";
+        assert_eq!(text(&message_to_text(message)), "plain café");
+    }
+
+    #[test]
+    fn renders_html_when_it_is_the_only_alternative() {
+        let message = b"Content-Type: multipart/alternative; boundary=b\n\n--b\nContent-Type: text/html\n\n<p>only <b>html</b></p>\n--b--\n";
+        assert!(text(&message_to_text(message)).contains("only html"));
+    }
+
+    #[test]
+    fn skips_attachments_and_binary_parts() {
+        let message = b"Content-Type: multipart/mixed; boundary=m\n\n\
+            --m\nContent-Type: text/plain\n\nvisible\n\
+            --m\nContent-Type: text/plain\nContent-Disposition: attachment; filename=notes.txt\n\nattached\n\
+            --m\nContent-Type: image/png\nContent-Transfer-Encoding: base64\n\niVBORw0KGgo=\n\
+            --m--\n";
+        assert_eq!(text(&message_to_text(message)), "visible");
+    }
+
+    #[test]
+    fn warns_about_multipart_without_boundary() {
+        let parsed = message_to_text(b"Content-Type: multipart/mixed\n\nbody\n");
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(parsed.text.is_empty());
+        assert!(parsed.sections.is_empty());
+    }
+
+    #[test]
+    fn reads_cut_off_multipart() {
+        let parts = split_multipart(b"preamble\n--x\n\nfirst\n--x\n\nsecond\n", "x");
+        assert_eq!(parts, [b"\nfirst\n".as_slice(), b"\nsecond\n".as_slice()]);
+    }
+}
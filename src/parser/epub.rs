@@ -0,0 +1,378 @@
+use std::{collections::HashMap, path::Path, fs::File, io::{Read, Seek}};
+
+use xml::{EventReader, reader::XmlEvent};
+use zip::ZipArchive;
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, docx::resolve_target, html::xhtml_to_text};
+
+const CONTAINER: &str = "META-INF/container.xml";
+
+/// A file of the book listed in the manifest of the package document.
+struct ManifestItem {
+    href: String,
+    media_type: String,
+    properties: String
+}
+
+/// The parts of the package document (the `.opf` file) needed to read the book.
+struct Package {
+    manifest: HashMap<String, ManifestItem>,
+    /// Ids of the manifest items in reading order.
+    spine: Vec<String>,
+    /// Id of the EPUB 2 table of contents.
+    ncx: Option<String>
+}
+
+/// Targets and titles of the entries of a table of contents.
+type TocEntries = Vec<(String, String)>;
+
+/// Extracts the text of an EPUB book chapter by chapter, in the reading order of the spine.
+/// Every chapter in the table of contents becomes a section titled after its entry, other files
+/// of the spine like the cover are part of the section before them. Chapters that can't be read become warnings.
+pub fn get_epub(path: &Path) -> Result<ParsedText, ParserError> {
+    let mut parsed = ParsedText::default();
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    let package = read_part(CONTAINER, &mut zip, read_rootfile)?
+        .ok_or_else(|| ParserError::corrupt("no package document in container.xml").within(CONTAINER))?;
+    let dir = package.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let Package { manifest, spine, ncx } = read_part(&package, &mut zip, read_package)?;
+
+    let titles = match table_of_contents(&manifest, ncx.as_deref(), dir, &mut zip) {
+        Ok(titles) => titles,
+        Err(e) => {
+            parsed.warn(e.with_path(path));
+            HashMap::new()
+        }
+    };
+
+    for id in spine {
+        let Some(item) = manifest.get(&id) else {
+            continue;
+        };
+        let href = resolve_target(dir, &item.href);
+
+        let text = zip.by_name(&href).map_err(ParserError::from).and_then(xhtml_to_text);
+        let text = match text {
+            Ok(text) => text,
+            Err(e) => {
+                parsed.warn(e.within(href.as_str()).with_path(path));
+                continue;
+            }
+        };
+        for warning in text.warnings {
+            parsed.warn(warning.within(href.as_str()).with_path(path));
+        }
+        if text.text.iter().all(|c| c.is_whitespace()) {
+            continue;
+        }
+
+        if !parsed.text.is_empty() {
+            parsed.text.push(' ');
+        }
+        if let Some((chapter, title)) = titles.get(&href) {
+            parsed.sections.push(Section { title: format!("Chapter {chapter}: {title}"), anchor: None, level: None, start: parsed.text.len() });
+        }
+        parsed.text.extend(text.text);
+    }
+
+    Ok(parsed)
+}
+
+/// Runs `read` on a part of the zip, adding the part to the location of errors.
+fn read_part<T, R: Read + Seek>(name: &str, zip: &mut ZipArchive<R>, read: impl FnOnce(&mut dyn Read) -> Result<T, ParserError>) -> Result<T, ParserError> {
+    let mut part = zip.by_name(name).map_err(|e| ParserError::from(e).within(name))?;
+    read(&mut part).map_err(|e| e.within(name))
+}
+
+/// Path of the package document (the `.opf` file) in `container.xml`.
+fn read_rootfile(container: &mut dyn Read) -> Result<Option<String>, ParserError> {
+    for event in EventReader::new(container) {
+        if let XmlEvent::StartElement { name, attributes, .. } = event? {
+            if name.local_name == "rootfile" {
+                return Ok(attributes.into_iter().find(|a| a.name.local_name == "full-path").map(|a| a.value));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn read_package(package: &mut dyn Read) -> Result<Package, ParserError> {
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    let mut ncx = None;
+
+    for event in EventReader::new(package) {
+        if let XmlEvent::StartElement { name, attributes, .. } = event? {
+            let attribute = |n: &str| attributes.iter().find(|a| a.name.local_name == n).map(|a| a.value.clone());
+
+            match name.local_name.as_str() {
+                "item" => if let (Some(id), Some(href)) = (attribute("id"), attribute("href")) {
+                    let media_type = attribute("media-type").unwrap_or_default();
+                    let properties = attribute("properties").unwrap_or_default();
+                    manifest.insert(id, ManifestItem { href: percent_decode(&href), media_type, properties });
+                },
+                "spine" => ncx = attribute("toc"),
+                "itemref" => if let Some(id) = attribute("idref") {
+                    spine.push(id);
+                },
+                _ => ()
+            }
+        }
+    }
+
+    Ok(Package { manifest, spine, ncx })
+}
+
+/// Numbers and titles of the chapters by their path in the zip, from the EPUB 3 navigation document
+/// or else from the EPUB 2 NCX file. Chapters are numbered in the order of the table of contents.
+fn table_of_contents<R: Read + Seek>(manifest: &HashMap<String, ManifestItem>, ncx: Option<&str>, dir: &str, zip: &mut ZipArchive<R>) -> Result<HashMap<String, (usize, String)>, ParserError> {
+    let nav = manifest.values().find(|item| item.properties.split_whitespace().any(|p| p == "nav"));
+    let ncx = ncx.and_then(|id| manifest.get(id))
+        .or_else(|| manifest.values().find(|item| item.media_type == "application/x-dtbncx+xml"));
+
+    let (item, is_nav) = match (nav, ncx) {
+        (Some(nav), _) => (nav, true),
+        (None, Some(ncx)) => (ncx, false),
+        (None, None) => return Ok(HashMap::new())
+    };
+
+    let href = resolve_target(dir, &item.href);
+    let toc_dir = href.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let entries = if is_nav {
+        read_part(&href, zip, read_nav)?
+    }else{
+        read_part(&href, zip, read_ncx)?
+    };
+
+    let mut titles = HashMap::new();
+    for (target, title) in entries {
+        // Entries can point at anchors inside a chapter, the first entry of a file titles the chapter.
+        let file = target.split('#').next().unwrap_or_default();
+        let chapter = titles.len() + 1;
+        titles.entry(resolve_target(toc_dir, &percent_decode(file))).or_insert((chapter, title));
+    }
+
+    Ok(titles)
+}
+
+/// Decodes the `%XX` escapes of an href, as in `Chapter%201.xhtml`, to get the name of the file in the zip.
+/// Hrefs whose escapes aren't valid UTF-8 are kept as they are.
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| href.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| href.to_string())
+}
+
+/// Targets and titles of the links in the `toc` navigation of an EPUB 3 navigation document.
+fn read_nav(nav: &mut dyn Read) -> Result<TocEntries, ParserError> {
+    let mut entries = Vec::new();
+    let mut toc_depth = 0;
+    let mut link: Option<(String, String)> = None;
+
+    for event in EventReader::new(nav) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                if name.local_name == "nav" && (toc_depth > 0 || attributes.iter().any(|a| a.name.local_name == "type" && a.value.split_whitespace().any(|t| t == "toc"))) {
+                    toc_depth += 1;
+                }else if name.local_name == "a" && toc_depth > 0 {
+                    link = attributes.into_iter().find(|a| a.name.local_name == "href").map(|a| (a.value, String::new()));
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "nav" && toc_depth > 0 {
+                    toc_depth -= 1;
+                }else if name.local_name == "a" {
+                    if let Some((href, title)) = link.take() {
+                        entries.push((href, title.split_whitespace().collect::<Vec<_>>().join(" ")));
+                    }
+                }
+            },
+            XmlEvent::Characters(chars) | XmlEvent::Whitespace(chars) => {
+                if let Some((_, title)) = link.as_mut() {
+                    title.push_str(&chars);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Targets and labels of the navigation points of an EPUB 2 NCX file.
+fn read_ncx(ncx: &mut dyn Read) -> Result<TocEntries, ParserError> {
+    let mut entries = Vec::new();
+    let mut label = String::new();
+    let mut in_label = false;
+
+    for event in EventReader::new(ncx) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => match name.local_name.as_str() {
+                "navLabel" => {
+                    in_label = true;
+                    label.clear();
+                },
+                "content" => if let Some(src) = attributes.into_iter().find(|a| a.name.local_name == "src") {
+                    entries.push((src.value, label.trim().to_string()));
+                },
+                _ => ()
+            },
+            XmlEvent::EndElement { name } if name.local_name == "navLabel" => in_label = false,
+            XmlEvent::Characters(chars) if in_label => label.push_str(&chars),
+            _ => ()
+        }
+    }
+
+    Ok(entries)
+}
+
+pub struct EpubParser;
+
+impl DocumentParser for EpubParser {
+    fn name(&self) -> &str {
+        "EPUB"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["epub"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/epub+zip"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_epub(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::{ZipWriter, write::FileOptions};
+
+    fn archive(parts: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn package(items: &str) -> Package {
+        let opf = format!(r#"<package xmlns="http://www.idpf.org/2007/opf"><manifest>{items}</manifest><spine toc="ncx"><itemref idref="c1"/></spine></package>"#);
+        read_package(&mut opf.as_bytes()).unwrap()
+    }
+
+    fn sorted(titles: HashMap<String, (usize, String)>) -> Vec<(String, usize, String)> {
+        let mut titles: Vec<_> = titles.into_iter().map(|(href, (chapter, title))| (href, chapter, title)).collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("Chapter%201.xhtml"), "Chapter 1.xhtml");
+        assert_eq!(percent_decode("%C3%A9t%C3%A9.xhtml"), "été.xhtml");
+        assert_eq!(percent_decode("100%.xhtml"), "100%.xhtml");
+        assert_eq!(percent_decode("%+1%zz%"), "%+1%zz%");
+        assert_eq!(percent_decode("%FF.xhtml"), "%FF.xhtml");
+    }
+
+    #[test]
+    fn reads_the_toc_of_the_navigation_document_only() {
+        let nav = r##"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+            <nav epub:type="landmarks"><ol><li><a href="cover.xhtml">Cover</a></li></ol></nav>
+            <nav epub:type="toc"><ol>
+                <li><a href="one.xhtml">The
+                    <span>Beginning</span></a>
+                    <ol><li><a href="one.xhtml#part">Part</a></li></ol>
+                </li>
+                <li><a href="Chapter%202.xhtml">End</a></li>
+            </ol></nav>
+        </body></html>"##;
+        let entries = read_nav(&mut nav.as_bytes()).unwrap();
+        assert_eq!(entries, [
+            ("one.xhtml".to_string(), "The Beginning".to_string()),
+            ("one.xhtml#part".to_string(), "Part".to_string()),
+            ("Chapter%202.xhtml".to_string(), "End".to_string())
+        ]);
+    }
+
+    #[test]
+    fn reads_the_nav_points_of_an_ncx() {
+        let ncx = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><docTitle><text>Book</text></docTitle><navMap>
+            <navPoint id="p1"><navLabel><text> First </text></navLabel><content src="text/one.xhtml"/>
+                <navPoint id="p2"><navLabel><text>Nested</text></navLabel><content src="text/one.xhtml#a"/></navPoint>
+            </navPoint>
+        </navMap></ncx>"#;
+        let entries = read_ncx(&mut ncx.as_bytes()).unwrap();
+        assert_eq!(entries, [
+            ("text/one.xhtml".to_string(), "First".to_string()),
+            ("text/one.xhtml#a".to_string(), "Nested".to_string())
+        ]);
+    }
+
+    #[test]
+    fn manifest_hrefs_are_decoded() {
+        let Package { manifest, spine, ncx } = package(r#"<item id="c1" href="Text/Chapter%201.xhtml" media-type="application/xhtml+xml"/>"#);
+        assert_eq!(manifest["c1"].href, "Text/Chapter 1.xhtml");
+        assert_eq!(resolve_target("OEBPS", &manifest["c1"].href), "OEBPS/Text/Chapter 1.xhtml");
+        assert_eq!(spine, ["c1"]);
+        assert_eq!(ncx.as_deref(), Some("ncx"));
+    }
+
+    #[test]
+    fn table_of_contents_prefers_the_navigation_document() {
+        let Package { manifest, ncx, .. } = package(r#"<item id="nav" href="nav/toc.xhtml" properties="nav" media-type="application/xhtml+xml"/><item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#);
+        let nav = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol>
+            <li><a href="../Chapter%201.xhtml">One</a></li><li><a href="../Chapter%201.xhtml#b">Again</a></li><li><a href="../two.xhtml">Two</a></li>
+        </ol></nav></body></html>"#;
+        let mut zip = archive(&[("OEBPS/nav/toc.xhtml", nav), ("OEBPS/toc.ncx", "not read")]);
+        let titles = table_of_contents(&manifest, ncx.as_deref(), "OEBPS", &mut zip).unwrap();
+        assert_eq!(sorted(titles), [
+            ("OEBPS/Chapter 1.xhtml".to_string(), 1, "One".to_string()),
+            ("OEBPS/two.xhtml".to_string(), 2, "Two".to_string())
+        ]);
+    }
+
+    #[test]
+    fn table_of_contents_falls_back_to_the_ncx() {
+        let Package { manifest, ncx, .. } = package(r#"<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#);
+        let ncx_file = r#"<ncx><navMap><navPoint><navLabel><text>Intro</text></navLabel><content src="Text/Chapter%201.xhtml"/></navPoint></navMap></ncx>"#;
+        let mut zip = archive(&[("toc.ncx", ncx_file)]);
+        let titles = table_of_contents(&manifest, ncx.as_deref(), "", &mut zip).unwrap();
+        assert_eq!(sorted(titles), [("Text/Chapter 1.xhtml".to_string(), 1, "Intro".to_string())]);
+    }
+
+    #[test]
+    fn no_table_of_contents_gives_no_titles() {
+        let Package { manifest, .. } = package(r#"<item id="c1" href="one.xhtml" media-type="application/xhtml+xml"/>"#);
+        let mut zip = archive(&[]);
+        assert!(table_of_contents(&manifest, None, "", &mut zip).unwrap().is_empty());
+    }
+}
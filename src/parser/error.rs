@@ -1,47 +1,178 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::{Path, PathBuf}};
 
 use pdf_extract::OutputError;
+use xml::common::Position;
 use zip::result::ZipError;
 
-pub enum ParserError {
+#[derive(Debug)]
+pub enum ParserErrorKind {
     ZipError(ZipError),
     IOError(std::io::Error),
     XmlError(xml::reader::Error),
-    PdfError(OutputError)
+    PdfError(OutputError),
+    /// The file is damaged or does not match its format.
+    Corrupt(String),
+    /// The file is valid, but uses a feature the parser does not support, like encryption.
+    Unsupported(String),
+    /// Reading the file was stopped at a limit, like the nesting depth or unpacked size of an archive.
+    LimitExceeded(String),
+    Panicked
+}
+
+/// Error of a parser, with the file and the format specific location it happened at.
+#[derive(Debug)]
+pub struct ParserError {
+    pub kind: ParserErrorKind,
+    pub path: Option<PathBuf>,
+    /// Where in the file the error happened, e.g. a zip entry, a line and column or a byte offset.
+    pub location: Option<String>
+}
+
+impl ParserError {
+    pub fn new(kind: ParserErrorKind) -> Self {
+        let location = match &kind {
+            ParserErrorKind::XmlError(e) => {
+                let position = e.position();
+                Some(format!("line {}, column {}", position.row + 1, position.column + 1))
+            },
+            ParserErrorKind::PdfError(OutputError::PdfError(e)) => pdf_location(e),
+            _ => None
+        };
+
+        ParserError { kind, path: None, location }
+    }
+
+    pub fn corrupt(message: impl Into<String>) -> Self {
+        ParserError::new(ParserErrorKind::Corrupt(message.into()))
+    }
+
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        ParserError::new(ParserErrorKind::Unsupported(message.into()))
+    }
+
+    pub fn limit_exceeded(message: impl Into<String>) -> Self {
+        ParserError::new(ParserErrorKind::LimitExceeded(message.into()))
+    }
+
+    /// Sets the path, unless the error already has one.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.to_path_buf());
+        }
+        self
+    }
+
+    /// Prefixes the location with `outer`, e.g. the zip entry that contains the xml line of the error.
+    pub fn within(mut self, outer: impl Into<String>) -> Self {
+        let outer = outer.into();
+        self.location = Some(match self.location {
+            Some(inner) => format!("{outer}, {inner}"),
+            None => outer
+        });
+        self
+    }
+
+    /// Name of the kind, used to group failures.
+    pub fn kind(&self) -> &'static str {
+        match self.kind {
+            ParserErrorKind::ZipError(_) => "ZipError",
+            ParserErrorKind::IOError(_) => "IOError",
+            ParserErrorKind::XmlError(_) => "XmlError",
+            ParserErrorKind::PdfError(_) => "PdfError",
+            ParserErrorKind::Corrupt(_) => "Corrupt",
+            ParserErrorKind::Unsupported(_) => "Unsupported",
+            ParserErrorKind::LimitExceeded(_) => "LimitExceeded",
+            ParserErrorKind::Panicked => "Panicked",
+        }
+    }
+
+    /// Whether the file uses a feature that isn't supported, as opposed to being damaged or unreadable.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self.kind,
+            ParserErrorKind::Unsupported(_) |
+            ParserErrorKind::ZipError(ZipError::UnsupportedArchive(_)))
+    }
+
+    /// Whether parsing the unchanged file again could succeed. Unsupported features and exceeded
+    /// limits fail the same way every time, so they are only parsed again once the file changes.
+    pub fn is_retryable(&self) -> bool {
+        !self.is_unsupported() && !matches!(self.kind, ParserErrorKind::LimitExceeded(_))
+    }
+
+    /// The error and its location, without the kind and the path.
+    pub fn message(&self) -> String {
+        let message = match &self.kind {
+            ParserErrorKind::ZipError(e) => e.to_string(),
+            ParserErrorKind::IOError(e) => e.to_string(),
+            ParserErrorKind::XmlError(e) => e.msg().to_string(),
+            ParserErrorKind::PdfError(e) => e.to_string(),
+            ParserErrorKind::Corrupt(m) | ParserErrorKind::Unsupported(m) | ParserErrorKind::LimitExceeded(m) => m.clone(),
+            ParserErrorKind::Panicked => "Parser panicked".to_string(),
+        };
+
+        match &self.location {
+            Some(location) => format!("{message} ({location})"),
+            None => message
+        }
+    }
+}
+
+fn pdf_location(e: &lopdf::Error) -> Option<String> {
+    match e {
+        lopdf::Error::Offset(offset) | lopdf::Error::Parse { offset } => Some(format!("byte {offset}")),
+        lopdf::Error::PageNumberNotFound(page) => Some(format!("page {page}")),
+        _ => None
+    }
+}
+
+impl From<ParserErrorKind> for ParserError {
+    fn from(value: ParserErrorKind) -> Self {
+        ParserError::new(value)
+    }
 }
 
 impl From<std::io::Error> for ParserError {
     fn from(value: std::io::Error) -> Self {
-        ParserError::IOError(value)
+        ParserError::new(ParserErrorKind::IOError(value))
     }
 }
 
 impl From<ZipError> for ParserError {
     fn from(value: ZipError) -> Self {
-        ParserError::ZipError(value)
+        ParserError::new(ParserErrorKind::ZipError(value))
     }
 }
 
 impl From<xml::reader::Error> for ParserError{
     fn from(value: xml::reader::Error) -> Self {
-        ParserError::XmlError(value)
+        ParserError::new(ParserErrorKind::XmlError(value))
     }
 
 }
 impl From<OutputError> for ParserError {
     fn from(value: OutputError) -> Self {
-        ParserError::PdfError(value)
+        ParserError::new(ParserErrorKind::PdfError(value))
     }
 
 }
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ParserError::ZipError(e) => writeln!(f, "ZipError: {e}"),
-            ParserError::IOError(e) => writeln!(f, "IOError: {e}"),
-            ParserError::XmlError(e) => writeln!(f, "XmlError: {e}"),
-            ParserError::PdfError(e) => writeln!(f, "PdfError: {e}"),
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "{}: {}", self.kind(), self.message())
+    }
+}
+
+impl std::error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ParserErrorKind::ZipError(e) => Some(e),
+            ParserErrorKind::IOError(e) => Some(e),
+            ParserErrorKind::XmlError(e) => Some(e),
+            ParserErrorKind::PdfError(e) => Some(e),
+            _ => None
         }
     }
 }
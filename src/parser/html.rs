@@ -1,8 +1,8 @@
-use std::{path::Path, io::BufReader, char};
+use std::{path::Path, io::{BufReader, Read}, char};
 
 use xml::EventReader;
 
-use super::error::ParserError;
+use super::{ParsedText, error::ParserError, registry::DocumentParser, sniff::text_starts_with};
 
 pub fn get_html_text(path: &Path) -> Result<Vec<char>, ParserError> {
 
@@ -12,15 +12,28 @@ pub fn get_html_text(path: &Path) -> Result<Vec<char>, ParserError> {
 
 
 
-    Ok(html2text::from_read(reader, usize::MAX).replace('\r', "").replace('\n', " ").chars().collect())
+    Ok(html_to_text(reader))
 }
 
-pub fn get_xhtml(path: &Path) -> Result<Vec<char>, ParserError> {
-    let file = std::fs::File::open(path).unwrap();
-    let reader = BufReader::new(file);
+/// Renders html read from `reader` as text on a single line.
+pub fn html_to_text(reader: impl Read) -> Vec<char> {
+    html2text::from_read(reader, usize::MAX).replace('\r', "").replace('\n', " ").chars().collect()
+}
+
+/// Extracts the character data of an xhtml or xml file. A syntax error after some text was read
+/// keeps that text and becomes a warning.
+pub fn get_xhtml(path: &Path) -> Result<ParsedText, ParserError> {
+    let file = std::fs::File::open(path)?;
+    let mut parsed = xhtml_to_text(BufReader::new(file))?;
+    parsed.warnings = parsed.warnings.into_iter().map(|w| w.with_path(path)).collect();
+    Ok(parsed)
+}
 
+/// Extracts the character data of xhtml or xml read from `reader`, see [`get_xhtml`].
+pub fn xhtml_to_text(reader: impl Read) -> Result<ParsedText, ParserError> {
     let event_reader = EventReader::new(reader);
     let mut content = String::new();
+    let mut parsed = ParsedText::default();
 
     for evn in event_reader {
         match evn {
@@ -29,7 +42,12 @@ pub fn get_xhtml(path: &Path) -> Result<Vec<char>, ParserError> {
                 content.push(' ');
             },
             Err(e) => {
-                return Err(ParserError::XmlError(e))
+                if content.trim().is_empty() {
+                    return Err(ParserError::from(e))
+                }
+
+                parsed.warn(ParserError::from(e));
+                break;
 
             },
             _ => ()
@@ -37,5 +55,54 @@ pub fn get_xhtml(path: &Path) -> Result<Vec<char>, ParserError> {
 
     }
 
-    Ok(content.replace('\r', "").replace('\n', " ").chars().collect())
+    parsed.text = content.replace('\r', "").replace('\n', " ").chars().collect();
+    Ok(parsed)
+}
+
+pub struct HtmlParser;
+
+impl DocumentParser for HtmlParser {
+    fn name(&self) -> &str {
+        "HTML"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["html", "htm"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["text/html"]
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        text_starts_with(head, "<!doctype html") || text_starts_with(head, "<html")
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_html_text(path).map(ParsedText::from)
+    }
+}
+
+pub struct XhtmlParser;
+
+impl DocumentParser for XhtmlParser {
+    fn name(&self) -> &str {
+        "XHTML"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xhtml", "xml"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/xhtml+xml", "application/xml", "text/xml"]
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        text_starts_with(head, "<?xml")
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_xhtml(path)
+    }
 }
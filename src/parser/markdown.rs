@@ -0,0 +1,414 @@
+use std::{collections::HashMap, path::Path};
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, txt::decode_text};
+
+pub fn parse_markdown(file: &Path) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(file)?;
+    Ok(markdown_to_text(&decode_text(&bytes)))
+}
+
+/// Strips the markdown syntax from `source`. Headings, code blocks and the text of links and images
+/// are kept, every heading starts a section that links to its slug.
+pub fn markdown_to_text(source: &str) -> ParsedText {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut parsed = ParsedText::default();
+    let mut slugs: HashMap<String, usize> = HashMap::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut i = front_matter_end(&lines);
+
+    while i < lines.len() {
+        let line = lines[i].trim_start();
+        i += 1;
+
+        if let Some((marker, len)) = fence {
+            if fence_marker(line).is_some_and(|(m, l)| m == marker && l >= len && line.trim_start_matches(marker).trim().is_empty()) {
+                fence = None;
+            }else{
+                push_text(&mut parsed, lines[i - 1]);
+            }
+            continue;
+        }
+
+        if let Some(marker) = fence_marker(line) {
+            fence = Some(marker);
+            continue;
+        }
+
+        if let Some((level, title)) = atx_heading(line) {
+            push_heading(&mut parsed, &mut slugs, level, title);
+            continue;
+        }
+
+        if let Some(level) = lines.get(i).and_then(|next| setext_level(next)) {
+            if is_paragraph(line) {
+                push_heading(&mut parsed, &mut slugs, level, line);
+                i += 1;
+                continue;
+            }
+        }
+
+        if is_thematic_break(line) || is_table_separator(line) || is_link_definition(line) {
+            continue;
+        }
+
+        push_text(&mut parsed, &strip_inline(strip_block_markers(line)));
+    }
+
+    parsed
+}
+
+/// Index of the first line after a YAML front matter block, or 0 without one.
+fn front_matter_end(lines: &[&str]) -> usize {
+    if lines.first().map(|l| l.trim_end()) != Some("---") {
+        return 0;
+    }
+
+    lines.iter().skip(1).position(|l| matches!(l.trim_end(), "---" | "..."))
+        .map(|end| end + 2)
+        .unwrap_or(0)
+}
+
+/// The marker character and length of a code fence like ` ``` ` or `~~~`.
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let marker = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then_some((marker, len))
+}
+
+fn atx_heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    // A closing sequence of `#` is only part of the syntax when it is separated by a space.
+    let title = rest.trim();
+    let without_closing = title.trim_end_matches('#');
+    let title = if without_closing.is_empty() || without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end()
+    }else{
+        title
+    };
+
+    Some((level as u8, title))
+}
+
+/// Level of a heading underlined by `line`, 1 for `===` and 2 for `---`.
+fn setext_level(line: &str) -> Option<u8> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    if line.chars().all(|c| c == '=') {
+        Some(1)
+    }else if line.chars().all(|c| c == '-') {
+        Some(2)
+    }else{
+        None
+    }
+}
+
+/// Whether the line is plain paragraph text, which can be turned into a heading by an underline.
+fn is_paragraph(line: &str) -> bool {
+    !line.is_empty() && strip_block_markers(line).len() == line.len() && !line.starts_with('|')
+}
+
+fn is_thematic_break(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 && matches!(chars[0], '-' | '*' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+fn is_table_separator(line: &str) -> bool {
+    line.contains('-') && line.contains('|') && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// A reference definition like `[label]: https://example.com`, whose url is not text.
+fn is_link_definition(line: &str) -> bool {
+    line.starts_with('[') && line.find("]:").is_some_and(|end| !line[1..end].contains(']'))
+}
+
+/// Removes the quote and list markers at the start of a line.
+fn strip_block_markers(mut line: &str) -> &str {
+    loop {
+        let before = line;
+
+        if let Some(rest) = line.strip_prefix('>') {
+            line = rest.trim_start();
+        }
+
+        for marker in ["- ", "* ", "+ "] {
+            if let Some(rest) = line.strip_prefix(marker) {
+                line = rest.trim_start();
+            }
+        }
+
+        let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") ")) {
+            line = line[digits + 1..].trim_start();
+        }
+
+        for task in ["[ ] ", "[x] ", "[X] "] {
+            if let Some(rest) = line.strip_prefix(task) {
+                line = rest.trim_start();
+            }
+        }
+
+        if line.len() == before.len() {
+            return line;
+        }
+    }
+}
+
+/// Removes emphasis, code span and html syntax and replaces links and images with their text.
+fn strip_inline(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if i + 1 < chars.len() && chars[i + 1].is_ascii_punctuation() => {
+                out.push(chars[i + 1]);
+                i += 2;
+            },
+            '`' => {
+                let ticks = chars[i..].iter().take_while(|c| **c == '`').count();
+                let close = (i + ticks..chars.len())
+                    .find(|j| chars[*j..].iter().take_while(|c| **c == '`').count() == ticks && chars[*j - 1] != '`');
+                match close {
+                    Some(end) => {
+                        out.extend(&chars[i + ticks..end]);
+                        i = end + ticks;
+                    },
+                    None => {
+                        out.extend(&chars[i..i + ticks]);
+                        i += ticks;
+                    }
+                }
+            },
+            '!' if chars.get(i + 1) == Some(&'[') => i += 1,
+            '[' => match link_end(&chars, i) {
+                Some((text_end, end)) => {
+                    out.push_str(&strip_inline(&chars[i + 1..text_end].iter().collect::<String>()));
+                    i = end;
+                },
+                None => {
+                    out.push(c);
+                    i += 1;
+                }
+            },
+            '<' => match chars[i..].iter().position(|c| *c == '>') {
+                Some(len) if is_tag(&chars[i + 1..i + len]) => {
+                    let inner: String = chars[i + 1..i + len].iter().collect();
+                    // Autolinks keep their url, html tags are dropped.
+                    if inner.contains("://") || inner.contains('@') {
+                        out.push_str(&inner);
+                    }
+                    out.push(' ');
+                    i += len + 1;
+                },
+                _ => {
+                    out.push(c);
+                    i += 1;
+                }
+            },
+            '*' | '~' => i += 1,
+            '_' => {
+                // Underscores inside words, like in snake_case, are not emphasis.
+                let inside_word = i > 0 && chars[i - 1].is_alphanumeric()
+                    && chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+                if inside_word {
+                    out.push(c);
+                }
+                i += 1;
+            },
+            '|' => {
+                out.push(' ');
+                i += 1;
+            },
+            _ => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// End of the text and end of a link or image starting with the `[` at `start`:
+/// `[text](url)`, `[text][label]` or `[text]`.
+fn link_end(chars: &[char], start: usize) -> Option<(usize, usize)> {
+    let text_end = matching(chars, start, '[', ']')?;
+
+    match chars.get(text_end + 1) {
+        Some('(') => matching(chars, text_end + 1, '(', ')').map(|end| (text_end, end + 1)),
+        Some('[') => matching(chars, text_end + 1, '[', ']').map(|end| (text_end, end + 1)),
+        _ => Some((text_end, text_end + 1))
+    }
+}
+
+/// Index of the bracket closing the one at `start`, taking nested brackets into account.
+fn matching(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        if *c == open {
+            depth += 1;
+        }else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Whether the text between `<` and `>` is an html tag, comment or autolink.
+fn is_tag(inner: &[char]) -> bool {
+    let inner = inner.strip_prefix(&['/']).unwrap_or(inner);
+    inner.first().is_some_and(|c| c.is_ascii_alphabetic()) || inner.starts_with(&['!', '-', '-'])
+}
+
+fn push_text(parsed: &mut ParsedText, text: &str) {
+    if text.trim().is_empty() {
+        return;
+    }
+
+    if !parsed.text.is_empty() {
+        parsed.text.push(' ');
+    }
+    parsed.text.extend(text.chars().filter(|c| *c != '\r'));
+}
+
+fn push_heading(parsed: &mut ParsedText, slugs: &mut HashMap<String, usize>, level: u8, title: &str) {
+    let title = strip_inline(title).trim().to_string();
+    if title.is_empty() {
+        return;
+    }
+
+    let slug = slugify(&title);
+    let count = slugs.entry(slug.clone()).or_insert(0);
+    let anchor = if *count == 0 {
+        slug
+    }else{
+        format!("{slug}-{count}")
+    };
+    *count += 1;
+
+    let start = if parsed.text.is_empty() { 0 } else { parsed.text.len() + 1 };
+    parsed.sections.push(Section { title: title.clone(), anchor: Some(anchor), level: Some(level), start });
+    push_text(parsed, &title);
+}
+
+/// Anchor of a heading the way GitHub generates it: lowercase, punctuation removed and spaces replaced by `-`.
+pub fn slugify(title: &str) -> String {
+    title.chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+pub struct MarkdownParser;
+
+impl DocumentParser for MarkdownParser {
+    fn name(&self) -> &str {
+        "Markdown"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["md", "markdown"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["text/markdown"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        parse_markdown(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(source: &str) -> String {
+        markdown_to_text(source).text.iter().collect()
+    }
+
+    fn headings(source: &str) -> Vec<(String, String, u8)> {
+        markdown_to_text(source).sections.into_iter()
+            .map(|s| (s.title, s.anchor.unwrap_or_default(), s.level.unwrap_or(0)))
+            .collect()
+    }
+
+    #[test]
+    fn reads_atx_headings() {
+        assert_eq!(atx_heading("## Install ##"), Some((2, "Install")));
+        assert_eq!(atx_heading("# C#"), Some((1, "C#")));
+        assert_eq!(atx_heading("#"), Some((1, "")));
+        assert_eq!(atx_heading("#hashtag"), None);
+        assert_eq!(atx_heading("####### seven"), None);
+    }
+
+    #[test]
+    fn reads_setext_headings() {
+        let found = headings("Title\n=====\n\nUsage\n---\ntext\n\n- item\n---\n");
+        assert_eq!(found, [("Title".to_string(), "title".to_string(), 1), ("Usage".to_string(), "usage".to_string(), 2)]);
+    }
+
+    #[test]
+    fn numbers_repeated_anchors() {
+        let anchors: Vec<String> = headings("# Notes\n## Notes\n# What's *new*?\n").into_iter().map(|h| h.1).collect();
+        assert_eq!(anchors, ["notes", "notes-1", "whats-new"]);
+    }
+
+    #[test]
+    fn sections_start_at_their_heading() {
+        let parsed = markdown_to_text("intro\n# Setup\nrun it\n");
+        let text: String = parsed.text.iter().collect();
+        assert_eq!(text, "intro Setup run it");
+        assert_eq!(parsed.sections[0].start, 6);
+    }
+
+    #[test]
+    fn keeps_fenced_code_verbatim() {
+        let source = "```rust\nlet x = *ptr; // [not](a link)\n```\n~~~\n# not a heading\n~~~~\nafter";
+        assert_eq!(text(source), "let x = *ptr; // [not](a link) # not a heading after");
+        assert!(markdown_to_text(source).sections.is_empty());
+    }
+
+    #[test]
+    fn keeps_text_of_links_and_images() {
+        assert_eq!(strip_inline("see [the *docs*](https://example.com/a_(b)) and ![logo][img]"), "see the docs and logo");
+        assert_eq!(strip_inline("mail <me@example.com> or <b>bold</b>"), "mail me@example.com  or  bold ");
+        assert_eq!(strip_inline("a < b and [unclosed"), "a < b and [unclosed");
+    }
+
+    #[test]
+    fn strips_emphasis_but_not_snake_case() {
+        assert_eq!(strip_inline("**bold** _em_ ~~gone~~ snake_case \\*literal\\*"), "bold em gone snake_case *literal*");
+        assert_eq!(strip_inline("``code with ` tick``"), "code with ` tick");
+    }
+
+    #[test]
+    fn skips_front_matter_tables_and_definitions() {
+        let source = "---\ntitle: secret\n---\n| a | b |\n|---|:-:|\n| 1 | 2 |\n\n[ref]: https://example.com\n***\n> - [x] done";
+        assert_eq!(text(source).split_whitespace().collect::<Vec<_>>(), ["a", "b", "1", "2", "done"]);
+    }
+
+    #[test]
+    fn slugifies_like_github() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("API v2 - Über_alles"), "api-v2---über_alles");
+    }
+}
@@ -1,5 +1,58 @@
 pub mod docx;
+pub mod pptx;
+pub mod xlsx;
 pub mod txt;
 pub mod html;
 pub mod pdf;
 pub mod error;
+pub mod sniff;
+pub mod registry;
+pub mod markdown;
+pub mod opendocument;
+pub mod epub;
+pub mod email;
+pub mod archive;
+pub mod code;
+pub mod rtf;
+pub mod notebook;
+pub mod csv;
+
+use error::ParserError;
+
+/// Text extracted from a file, together with the non-fatal errors of parts that could not be read.
+#[derive(Debug, Default)]
+pub struct ParsedText {
+    pub text: Vec<char>,
+    pub warnings: Vec<ParserError>,
+    /// Parts of the text search results can point at, ordered by their start.
+    pub sections: Vec<Section>,
+    /// Separate documents inside the file by their path inside it, like the messages of a mailbox.
+    /// A file with such documents is not indexed as a document itself, see [`crate::model::base::inner_path`].
+    pub documents: Vec<(String, ParsedText)>,
+    /// Source code, tokenized with [`crate::lexer::Lexer::new_code`] instead of as prose.
+    pub code: bool
+}
+
+/// A part of a document, like a heading and the text below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    /// Fragment that links to the section, e.g. the slug of a markdown heading.
+    pub anchor: Option<String>,
+    /// Heading level, 1 being the top. Terms in the titles of headings are boosted in the ranking.
+    pub level: Option<u8>,
+    /// Offset in [`ParsedText::text`] the section starts at. It ends where the next section starts.
+    pub start: usize
+}
+
+impl ParsedText {
+    pub fn warn(&mut self, warning: ParserError) {
+        self.warnings.push(warning);
+    }
+}
+
+impl From<Vec<char>> for ParsedText {
+    fn from(text: Vec<char>) -> Self {
+        ParsedText { text, ..Default::default() }
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config::get_config;
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, markdown::markdown_to_text};
+
+/// Extracts the markdown and code cells of a Jupyter notebook, and their text outputs with
+/// [`crate::config::Config::notebook_outputs`].
+pub fn parse_notebook(path: &Path) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    notebook_to_text(&bytes, get_config().notebook_outputs)
+}
+
+/// Extracts the markdown and code cells of a notebook, with the text outputs of code cells when `outputs` is set.
+/// Every cell becomes a section titled after its number, counting from 1. Raw cells and images are left out.
+pub fn notebook_to_text(bytes: &[u8], outputs: bool) -> Result<ParsedText, ParserError> {
+    let notebook: Value = serde_json::from_slice(bytes).map_err(|e| ParserError::corrupt(e.to_string()))?;
+
+    // Notebooks before nbformat 4 keep their cells in worksheets.
+    let cells: Vec<&Value> = match notebook.get("cells") {
+        Some(cells) => array(cells).collect(),
+        None => array(&notebook["worksheets"]).flat_map(|sheet| array(&sheet["cells"])).collect()
+    };
+    if cells.is_empty() && notebook.get("nbformat").is_none() {
+        return Err(ParserError::corrupt("no cells in notebook"));
+    }
+
+    let mut parsed = ParsedText::default();
+    for (i, cell) in cells.iter().enumerate() {
+        let text = match cell["cell_type"].as_str() {
+            Some("markdown") => markdown_to_text(&joined(&cell["source"])).text,
+            Some("code") => {
+                let source = if cell.get("source").is_some() { &cell["source"] } else { &cell["input"] };
+                let mut text: Vec<char> = joined(source).chars().collect();
+                if outputs {
+                    for output in array(&cell["outputs"]) {
+                        push_part(&mut text, &output_text(output));
+                    }
+                }
+                text
+            },
+            _ => continue
+        };
+
+        if text.iter().all(|c| c.is_whitespace()) {
+            continue;
+        }
+        if !parsed.text.is_empty() {
+            parsed.text.push(' ');
+        }
+        parsed.sections.push(Section { title: format!("Cell {}", i + 1), anchor: None, level: None, start: parsed.text.len() });
+        parsed.text.extend(text);
+    }
+
+    Ok(parsed)
+}
+
+/// Text of a stream, a result shown as text or an error. Tracebacks are left out since they are full of
+/// terminal escape codes, the name and message of the error are kept.
+fn output_text(output: &Value) -> String {
+    match output["output_type"].as_str() {
+        Some("stream") => joined(&output["text"]),
+        Some("execute_result" | "display_data") => joined(&output["data"]["text/plain"]),
+        // nbformat 3 stores the text of results next to the output type.
+        Some("pyout" | "pyerr") if output.get("text").is_some() => joined(&output["text"]),
+        Some("error" | "pyerr") => format!("{} {}", output["ename"].as_str().unwrap_or(""), output["evalue"].as_str().unwrap_or("")),
+        _ => String::new()
+    }
+}
+
+fn array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+/// Multiline strings are stored as a string or as a list of lines that end with their line break.
+fn joined(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(|line| line.as_str()).collect(),
+        _ => String::new()
+    }
+}
+
+fn push_part(text: &mut Vec<char>, part: &str) {
+    if part.trim().is_empty() {
+        return;
+    }
+    if !text.is_empty() {
+        text.push(' ');
+    }
+    text.extend(part.chars());
+}
+
+pub struct NotebookParser;
+
+impl DocumentParser for NotebookParser {
+    fn name(&self) -> &str {
+        "Jupyter notebook"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ipynb"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/x-ipynb+json"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        parse_notebook(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(notebook: &str, outputs: bool) -> (String, Vec<String>) {
+        let parsed = notebook_to_text(notebook.as_bytes(), outputs).unwrap();
+        (parsed.text.iter().collect(), parsed.sections.into_iter().map(|s| s.title).collect())
+    }
+
+    const NOTEBOOK: &str = r##"{
+        "nbformat": 4,
+        "cells": [
+            {"cell_type": "markdown", "source": ["# Churn\n", "Monthly **churn** report"]},
+            {"cell_type": "raw", "source": "skipped raw"},
+            {"cell_type": "code", "source": "", "outputs": []},
+            {"cell_type": "code", "source": ["df = load()\n", "df.groupby('plan')"], "outputs": [
+                {"output_type": "stream", "name": "stdout", "text": ["loaded rows\n"]},
+                {"output_type": "execute_result", "data": {"text/plain": "plan totals", "image/png": "iVBORw0KGgo="}},
+                {"output_type": "error", "ename": "KeyError", "evalue": "'plan'", "traceback": ["\u001b[31mtrace"]}
+            ]}
+        ]
+    }"##;
+
+    #[test]
+    fn reads_cells_as_numbered_sections() {
+        let (text, titles) = parse(NOTEBOOK, false);
+        assert_eq!(titles, ["Cell 1", "Cell 4"]);
+        assert!(text.contains("Monthly churn report"), "{text}");
+        assert!(!text.contains("**"));
+        assert!(text.contains("df.groupby('plan')"));
+        assert!(!text.contains("skipped raw"));
+        assert!(!text.contains("loaded rows"));
+    }
+
+    #[test]
+    fn reads_text_outputs_when_enabled() {
+        let (text, _) = parse(NOTEBOOK, true);
+        assert!(text.ends_with("df.groupby('plan') loaded rows\n plan totals KeyError 'plan'"), "{text}");
+        assert!(!text.contains("iVBOR"));
+        assert!(!text.contains("trace"));
+    }
+
+    #[test]
+    fn reads_worksheets_of_nbformat_3() {
+        let notebook = r#"{"nbformat": 3, "worksheets": [{"cells": [
+            {"cell_type": "code", "input": "print('hi')", "outputs": [{"output_type": "pyout", "text": ["hi"]}]},
+            {"cell_type": "markdown", "source": "notes"}
+        ]}]}"#;
+        let (text, titles) = parse(notebook, true);
+        assert_eq!(text, "print('hi') hi notes");
+        assert_eq!(titles, ["Cell 1", "Cell 2"]);
+    }
+
+    #[test]
+    fn rejects_json_that_is_no_notebook() {
+        assert!(notebook_to_text(br#"{"name": "package"}"#, false).is_err());
+        assert!(notebook_to_text(b"{ not json", false).is_err());
+        assert!(notebook_to_text(br#"{"nbformat": 4, "cells": []}"#, false).is_ok());
+    }
+}
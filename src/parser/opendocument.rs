@@ -0,0 +1,280 @@
+use std::{path::Path, fs::File};
+
+use xml::{EventReader, name::OwnedName, reader::XmlEvent};
+use zip::{ZipArchive, result::ZipError};
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser};
+
+const OFFICE_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:office:1.0";
+const TEXT_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:text:1.0";
+const TABLE_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:table:1.0";
+const DRAW_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:drawing:1.0";
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const META_NS: &str = "urn:oasis:names:tc:opendocument:xmlns:meta:1.0";
+
+/// Spaces a `text:s` element adds at most.
+const MAX_SPACES: usize = 64;
+
+/// Extracts the text of an OpenDocument text, spreadsheet or presentation. The title, subject,
+/// description and keywords of `meta.xml` come first, then the body of `content.xml`.
+/// Headings, sheets and slides become sections. Only `content.xml` is required.
+pub fn get_opendocument(path: &Path) -> Result<ParsedText, ParserError> {
+    let mut parsed = ParsedText::default();
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    match zip.by_name("meta.xml") {
+        Ok(file) => if let Err(e) = parse_meta(EventReader::new(file), &mut parsed) {
+            parsed.warn(e.within("meta.xml").with_path(path));
+        },
+        Err(ZipError::FileNotFound) => (),
+        Err(e) => parsed.warn(ParserError::from(e).within("meta.xml").with_path(path))
+    }
+
+    let content = zip.by_name("content.xml").map_err(|e| ParserError::from(e).within("content.xml"))?;
+    parse_content(EventReader::new(content), &mut parsed).map_err(|e| e.within("content.xml"))?;
+
+    Ok(parsed)
+}
+
+fn is(name: &OwnedName, namespace: &str, local_name: &str) -> bool {
+    name.local_name == local_name && name.namespace.as_deref() == Some(namespace)
+}
+
+fn attribute<'a>(attributes: &'a [xml::attribute::OwnedAttribute], namespace: &str, local_name: &str) -> Option<&'a str> {
+    attributes.iter().find(|a| is(&a.name, namespace, local_name)).map(|a| a.value.as_str())
+}
+
+fn push_separator(parsed: &mut ParsedText) {
+    if parsed.text.last().is_some_and(|c| *c != ' ') {
+        parsed.text.push(' ');
+    }
+}
+
+fn parse_meta<R: std::io::Read>(parser: EventReader<R>, parsed: &mut ParsedText) -> Result<(), ParserError> {
+    let mut in_field = false;
+
+    for event in parser {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                in_field = ["title", "subject", "description"].iter().any(|n| is(&name, DC_NS, n)) || is(&name, META_NS, "keyword");
+            },
+            XmlEvent::EndElement { .. } => {
+                if in_field {
+                    push_separator(parsed);
+                }
+                in_field = false;
+            },
+            XmlEvent::Characters(chars) if in_field => parsed.text.extend(chars.chars()),
+            _ => ()
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_content<R: std::io::Read>(parser: EventReader<R>, parsed: &mut ParsedText) -> Result<(), ParserError> {
+    let mut in_body = false;
+    // Tables of a spreadsheet are sheets, tables in text documents are not sections.
+    let mut in_spreadsheet = false;
+    // Depth of nested paragraphs and headings, text outside of them is formatting or metadata.
+    let mut paragraphs = 0;
+    let mut heading: Option<(u8, usize)> = None;
+    let mut slides = 0;
+    // The author and date of comments, and text removed by tracked changes, are not part of the text.
+    let mut ignored = 0;
+
+    for event in parser {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                if is(&name, OFFICE_NS, "body") {
+                    in_body = true;
+                }else if !in_body {
+                    continue;
+                }
+
+                if is(&name, OFFICE_NS, "spreadsheet") {
+                    in_spreadsheet = true;
+                }else if name.namespace.as_deref() == Some(DC_NS) || is(&name, TEXT_NS, "tracked-changes") {
+                    ignored += 1;
+                }else if is(&name, TEXT_NS, "p") {
+                    paragraphs += 1;
+                    push_separator(parsed);
+                }else if is(&name, TEXT_NS, "h") {
+                    paragraphs += 1;
+                    let level = attribute(&attributes, TEXT_NS, "outline-level").and_then(|l| l.parse().ok()).unwrap_or(1);
+                    push_separator(parsed);
+                    heading = Some((level, parsed.text.len()));
+                }else if is(&name, TEXT_NS, "s") {
+                    // The count is untrusted, and more spaces make no difference to the search.
+                    let count: usize = attribute(&attributes, TEXT_NS, "c").and_then(|c| c.parse().ok()).unwrap_or(1);
+                    parsed.text.extend(std::iter::repeat_n(' ', count.min(MAX_SPACES)));
+                }else if is(&name, TEXT_NS, "tab") || is(&name, TEXT_NS, "line-break") {
+                    parsed.text.push(' ');
+                }else if is(&name, TABLE_NS, "table") && in_spreadsheet {
+                    if let Some(sheet) = attribute(&attributes, TABLE_NS, "name") {
+                        push_separator(parsed);
+                        parsed.sections.push(Section { title: sheet.to_string(), anchor: None, level: None, start: parsed.text.len() });
+                    }
+                }else if is(&name, DRAW_NS, "page") {
+                    slides += 1;
+                    push_separator(parsed);
+                    parsed.sections.push(Section { title: format!("Slide {slides}"), anchor: None, level: None, start: parsed.text.len() });
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                // Elements outside of the body are skipped at their end too, so the depths stay balanced.
+                if is(&name, OFFICE_NS, "body") {
+                    in_body = false;
+                }else if !in_body {
+                    continue;
+                }else if name.namespace.as_deref() == Some(DC_NS) || is(&name, TEXT_NS, "tracked-changes") {
+                    ignored -= 1;
+                }else if is(&name, TEXT_NS, "p") {
+                    paragraphs -= 1;
+                    push_separator(parsed);
+                }else if is(&name, TEXT_NS, "h") {
+                    paragraphs -= 1;
+                    if let Some((level, start)) = heading.take() {
+                        let title: String = parsed.text[start..].iter().collect::<String>().trim().to_string();
+                        if !title.is_empty() {
+                            parsed.sections.push(Section { title, anchor: None, level: Some(level), start });
+                        }
+                    }
+                    push_separator(parsed);
+                }else if is(&name, TABLE_NS, "table-cell") {
+                    push_separator(parsed);
+                }
+            },
+            XmlEvent::Characters(chars) | XmlEvent::Whitespace(chars) if paragraphs > 0 && ignored == 0 => {
+                parsed.text.extend(chars.chars().map(|c| if c.is_whitespace() { ' ' } else { c }));
+            },
+            _ => ()
+        }
+    }
+
+    Ok(())
+}
+
+/// Parser for the OpenDocument formats of LibreOffice and OpenOffice, including their templates.
+pub enum OpenDocumentParser {
+    Text,
+    Spreadsheet,
+    Presentation
+}
+
+impl DocumentParser for OpenDocumentParser {
+    fn name(&self) -> &str {
+        match self {
+            OpenDocumentParser::Text => "OpenDocument text",
+            OpenDocumentParser::Spreadsheet => "OpenDocument spreadsheet",
+            OpenDocumentParser::Presentation => "OpenDocument presentation",
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        match self {
+            OpenDocumentParser::Text => &["odt", "ott"],
+            OpenDocumentParser::Spreadsheet => &["ods", "ots"],
+            OpenDocumentParser::Presentation => &["odp", "otp"],
+        }
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        match self {
+            OpenDocumentParser::Text => &["application/vnd.oasis.opendocument.text", "application/vnd.oasis.opendocument.text-template"],
+            OpenDocumentParser::Spreadsheet => &["application/vnd.oasis.opendocument.spreadsheet", "application/vnd.oasis.opendocument.spreadsheet-template"],
+            OpenDocumentParser::Presentation => &["application/vnd.oasis.opendocument.presentation", "application/vnd.oasis.opendocument.presentation-template"],
+        }
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_opendocument(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMESPACES: &str = r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0""#;
+
+    fn content(body: &str) -> ParsedText {
+        let xml = format!(r#"<office:document-content {NAMESPACES}><office:automatic-styles><text:p>style</text:p></office:automatic-styles><office:body>{body}</office:body></office:document-content>"#);
+        let mut parsed = ParsedText::default();
+        parse_content(EventReader::new(xml.as_bytes()), &mut parsed).unwrap();
+        parsed
+    }
+
+    fn text(parsed: &ParsedText) -> String {
+        parsed.text.iter().collect::<String>().trim().to_string()
+    }
+
+    fn sections(parsed: &ParsedText) -> Vec<(String, Option<u8>, usize)> {
+        parsed.sections.iter().map(|s| (s.title.clone(), s.level, s.start)).collect()
+    }
+
+    #[test]
+    fn reads_paragraphs_of_the_body_only() {
+        let parsed = content("<office:text><text:p>Hello <text:span>world</text:span></text:p><text:p>again</text:p></office:text>");
+        assert_eq!(text(&parsed), "Hello world again");
+    }
+
+    #[test]
+    fn reads_headings_as_sections() {
+        let parsed = content(r#"<office:text><text:p>intro</text:p><text:h text:outline-level="2">Install <text:span>steps</text:span></text:h><text:p>run it</text:p><text:h/></office:text>"#);
+        assert_eq!(text(&parsed), "intro Install steps run it");
+        assert_eq!(sections(&parsed), [("Install steps".to_string(), Some(2), 6)]);
+    }
+
+    #[test]
+    fn skips_comment_metadata_and_tracked_deletions() {
+        let parsed = content("<office:text><text:tracked-changes><text:changed-region><text:deletion><text:p>removed</text:p></text:deletion></text:changed-region></text:tracked-changes>\
+            <text:p>kept<office:annotation><dc:creator>Alice</dc:creator><dc:date>2024-01-01</dc:date><text:p>the comment</text:p></office:annotation></text:p></office:text>");
+        assert_eq!(text(&parsed), "kept the comment");
+    }
+
+    #[test]
+    fn clamps_space_count() {
+        let parsed = content(r#"<office:text><text:p>a<text:s text:c="100000000"/>b<text:s/>c<text:tab/>d</text:p></office:text>"#);
+        let text: String = parsed.text.iter().collect();
+        assert_eq!(text.trim(), format!("a{}b c d", " ".repeat(MAX_SPACES)));
+    }
+
+    #[test]
+    fn reads_sheets_as_sections() {
+        let parsed = content(r#"<office:spreadsheet><table:table table:name="Budget"><table:table-row><table:table-cell><text:p>rent</text:p></table:table-cell><table:table-cell><text:p>900</text:p></table:table-cell></table:table-row></table:table><table:table table:name="Staff"><table:table-row><table:table-cell><text:p>alice</text:p></table:table-cell></table:table-row></table:table></office:spreadsheet>"#);
+        assert_eq!(text(&parsed), "rent 900 alice");
+        let titles: Vec<String> = sections(&parsed).into_iter().map(|(title, _, _)| title).collect();
+        assert_eq!(titles, ["Budget", "Staff"]);
+        assert_eq!(parsed.text[parsed.sections[1].start..].iter().collect::<String>().trim(), "alice");
+    }
+
+    #[test]
+    fn tables_in_text_documents_are_no_sections() {
+        let parsed = content(r#"<office:text><table:table table:name="Table1"><table:table-row><table:table-cell><text:p>cell</text:p></table:table-cell></table:table-row></table:table></office:text>"#);
+        assert_eq!(text(&parsed), "cell");
+        assert!(parsed.sections.is_empty());
+    }
+
+    #[test]
+    fn reads_slides_as_sections() {
+        let parsed = content(r#"<office:presentation><draw:page draw:name="intro"><draw:frame><draw:text-box><text:p>Welcome</text:p></draw:text-box></draw:frame></draw:page><draw:page><draw:frame><draw:text-box><text:p>Agenda</text:p></draw:text-box></draw:frame></draw:page></office:presentation>"#);
+        let titles: Vec<String> = sections(&parsed).into_iter().map(|(title, _, _)| title).collect();
+        assert_eq!(titles, ["Slide 1", "Slide 2"]);
+        assert_eq!(text(&parsed), "Welcome Agenda");
+    }
+
+    #[test]
+    fn reads_meta_fields() {
+        let xml = format!(r#"<office:document-meta {NAMESPACES}><office:meta><meta:generator>LibreOffice</meta:generator><dc:title>Report</dc:title><dc:subject>Q3</dc:subject><meta:keyword>budget</meta:keyword><dc:creator>Alice</dc:creator></office:meta></office:document-meta>"#);
+        let mut parsed = ParsedText::default();
+        parse_meta(EventReader::new(xml.as_bytes()), &mut parsed).unwrap();
+        assert_eq!(text(&parsed), "Report Q3 budget");
+    }
+
+    #[test]
+    fn fails_on_broken_xml() {
+        let mut parsed = ParsedText::default();
+        assert!(parse_content(EventReader::new("<office:body><text:p>".as_bytes()), &mut parsed).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::Path;
 
-use super::error::ParserError;
+use super::{ParsedText, error::ParserError, registry::DocumentParser};
 
 pub fn get_pdf(file: &Path) -> Result<Vec<char>, ParserError> {
     let bytes = std::fs::read(file)?;
@@ -8,3 +8,27 @@ pub fn get_pdf(file: &Path) -> Result<Vec<char>, ParserError> {
 
     Ok(out.replace('\r', "").replace('\n', " ").chars().collect())
 }
+
+pub struct PdfParser;
+
+impl DocumentParser for PdfParser {
+    fn name(&self) -> &str {
+        "PDF"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pdf"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/pdf"]
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        head.starts_with(b"%PDF-")
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_pdf(path).map(ParsedText::from)
+    }
+}
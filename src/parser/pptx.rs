@@ -0,0 +1,174 @@
+use std::{path::Path, fs::File, io::{Read, Seek}};
+
+use xml::{EventReader, reader::XmlEvent};
+use zip::ZipArchive;
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, docx::{zip_read, zip_option_read, zip_relationships}};
+
+const PRESENTATION: &str = "ppt/presentation.xml";
+
+/// Extracts the text of every slide and its speaker notes, in the order of the presentation.
+/// Every slide becomes a section. Slides and notes that can't be read become warnings.
+pub fn get_pptx(path: &Path) -> Result<ParsedText, ParserError> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let mut parsed = pptx_to_text(&mut zip)?;
+    parsed.warnings = parsed.warnings.into_iter().map(|w| w.with_path(path)).collect();
+    Ok(parsed)
+}
+
+fn pptx_to_text<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<ParsedText, ParserError> {
+    let mut parsed = ParsedText::default();
+
+    for (i, slide) in slide_order(zip)?.into_iter().enumerate() {
+        if !parsed.text.is_empty() {
+            parsed.text.push(' ');
+        }
+        parsed.sections.push(Section { title: format!("Slide {}", i + 1), anchor: None, level: None, start: parsed.text.len() });
+
+        match zip_read(slide.clone(), zip, "a:t") {
+            Ok(text) => parsed.text.extend(text.chars()),
+            Err(e) => parsed.warn(e)
+        }
+
+        let notes = zip_relationships(&slide, zip).map(|rels| {
+            rels.into_values().find(|r| r.kind.ends_with("/notesSlide")).map(|r| r.target)
+        });
+        let notes = match notes {
+            Ok(Some(notes)) => zip_option_read(notes, zip, "a:t"),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e)
+        };
+        match notes {
+            Ok(Some(text)) => parsed.text.extend(text.chars()),
+            Ok(None) => (),
+            Err(e) => parsed.warn(e)
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Slides in the order of the slide list of the presentation. Slides can be reordered
+/// without being renamed, so their file names don't tell the order.
+fn slide_order<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<Vec<String>, ParserError> {
+    let rels = zip_relationships(PRESENTATION, zip)?;
+    let presentation = zip.by_name(PRESENTATION).map_err(|e| ParserError::from(e).within(PRESENTATION))?;
+    let mut slides = Vec::new();
+
+    for event in EventReader::new(presentation) {
+        match event.map_err(|e| ParserError::from(e).within(PRESENTATION))? {
+            XmlEvent::StartElement { name, attributes, .. } if name.local_name == "sldId" => {
+                let slide = attributes.iter()
+                    .find(|a| a.name.local_name == "id" && a.name.namespace.as_deref().is_some_and(|ns| ns.ends_with("relationships")))
+                    .and_then(|id| rels.get(&id.value));
+                if let Some(slide) = slide {
+                    slides.push(slide.target.clone());
+                }
+            },
+            _ => ()
+        }
+    }
+
+    Ok(slides)
+}
+
+pub struct PptxParser;
+
+impl DocumentParser for PptxParser {
+    fn name(&self) -> &str {
+        "PPTX"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pptx"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/vnd.openxmlformats-officedocument.presentationml.presentation"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_pptx(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::{ZipWriter, write::FileOptions};
+
+    const REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+    fn archive(parts: &[(&str, String)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn rels(targets: &[(&str, &str, &str)]) -> String {
+        let rels: String = targets.iter()
+            .map(|(id, kind, target)| format!(r#"<Relationship Id="{id}" Type="{REL}/{kind}" Target="{target}"/>"#))
+            .collect();
+        format!(r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{rels}</Relationships>"#)
+    }
+
+    fn slide(text: &str) -> String {
+        format!(r#"<p:sld xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main"><a:p><a:r><a:t>{text}</a:t></a:r></a:p></p:sld>"#)
+    }
+
+    fn presentation(ids: &[&str]) -> String {
+        let ids: String = ids.iter().enumerate().map(|(i, id)| format!(r#"<p:sldId id="{}" r:id="{id}"/>"#, 256 + i)).collect();
+        format!(r#"<p:presentation xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main" xmlns:r="{REL}"><p:sldIdLst>{ids}</p:sldIdLst></p:presentation>"#)
+    }
+
+    fn text(parsed: &ParsedText) -> String {
+        parsed.text.iter().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn reads_slides_in_the_order_of_the_presentation() {
+        let mut zip = archive(&[
+            (PRESENTATION, presentation(&["rId3", "rId2"])),
+            ("ppt/_rels/presentation.xml.rels", rels(&[("rId2", "slide", "slides/slide1.xml"), ("rId3", "slide", "/ppt/slides/slide2.xml")])),
+            ("ppt/slides/slide1.xml", slide("second")),
+            ("ppt/slides/slide2.xml", slide("first"))
+        ]);
+        let parsed = pptx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "first second");
+        let titles: Vec<_> = parsed.sections.iter().map(|s| (s.title.as_str(), s.start)).collect();
+        assert_eq!(titles[0], ("Slide 1", 0));
+        assert_eq!(titles[1].0, "Slide 2");
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn reads_notes_through_the_relationships_of_the_slide() {
+        let mut zip = archive(&[
+            (PRESENTATION, presentation(&["rId1"])),
+            ("ppt/_rels/presentation.xml.rels", rels(&[("rId1", "slide", "slides/slide1.xml")])),
+            ("ppt/slides/slide1.xml", slide("title")),
+            ("ppt/slides/_rels/slide1.xml.rels", rels(&[("rId1", "slideLayout", "../slideLayouts/slideLayout1.xml"), ("rId2", "notesSlide", "../notesSlides/notesSlide7.xml")])),
+            ("ppt/notesSlides/notesSlide7.xml", slide("spoken"))
+        ]);
+        let parsed = pptx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "title spoken");
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn missing_slide_becomes_a_warning() {
+        let mut zip = archive(&[
+            (PRESENTATION, presentation(&["rId1", "rId2"])),
+            ("ppt/_rels/presentation.xml.rels", rels(&[("rId1", "slide", "slides/slide1.xml"), ("rId2", "slide", "slides/slide2.xml")])),
+            ("ppt/slides/slide2.xml", slide("kept"))
+        ]);
+        let parsed = pptx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "kept");
+        assert_eq!(parsed.sections.len(), 2);
+        assert_eq!(parsed.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{path::Path, sync::OnceLock};
+
+use crate::config::{get_config, CodeMode};
+
+use super::{ParsedText, error::ParserError, sniff::sniff_mime_type, docx::DocxParser, pptx::PptxParser, xlsx::XlsxParser, html::{HtmlParser, XhtmlParser}, markdown::MarkdownParser, opendocument::OpenDocumentParser, epub::EpubParser, email::EmailParser, archive::ArchiveParser, code::{CodeParser, LANGUAGES}, rtf::RtfParser, notebook::NotebookParser, csv::CsvParser, pdf::PdfParser, txt::TxtParser};
+
+/// Parsers used by the indexer and the watcher. Library users can set their own registry,
+/// e.g. the defaults plus their own formats, before anything is indexed.
+pub static GLOB_PARSERS: OnceLock<ParserRegistry> = OnceLock::new();
+
+/// Returns the global registry, or the default parsers when none was set.
+pub fn get_parsers() -> &'static ParserRegistry {
+    GLOB_PARSERS.get_or_init(ParserRegistry::with_defaults)
+}
+
+/// A file format the indexer can extract text from.
+pub trait DocumentParser: Send + Sync {
+    /// Name of the format, used in logs.
+    fn name(&self) -> &str;
+
+    /// File extensions without the leading dot, in lowercase.
+    fn extensions(&self) -> &[&str];
+
+    fn mime_types(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Recognizes the format from the first bytes of a file. Used for files without an extension
+    /// whose type could not be told from their magic bytes.
+    fn sniff(&self, _head: &[u8]) -> bool {
+        false
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError>;
+}
+
+#[derive(Default)]
+pub struct ParserRegistry {
+    parsers: Vec<Box<dyn DocumentParser>>
+}
+
+impl ParserRegistry {
+    pub fn new() -> Self {
+        ParserRegistry { parsers: Vec::new() }
+    }
+
+    pub fn with_defaults() -> Self {
+        let mut registry = ParserRegistry::new();
+        // Plain text sniffs any file that doesn't look binary, so it is registered first to be tried last.
+        registry.register(TxtParser);
+        registry.register(MarkdownParser);
+        registry.register(DocxParser);
+        registry.register(PptxParser);
+        registry.register(XlsxParser);
+        registry.register(HtmlParser);
+        registry.register(XhtmlParser);
+        registry.register(PdfParser);
+        registry.register(OpenDocumentParser::Text);
+        registry.register(OpenDocumentParser::Spreadsheet);
+        registry.register(OpenDocumentParser::Presentation);
+        registry.register(EpubParser);
+        registry.register(RtfParser);
+        registry.register(NotebookParser);
+        registry.register(CsvParser::Csv);
+        registry.register(CsvParser::Tsv);
+        registry.register(EmailParser::Message);
+        registry.register(EmailParser::Mailbox);
+        registry.register(ArchiveParser::Zip);
+        registry.register(ArchiveParser::Tar);
+        registry.register(ArchiveParser::Gzip);
+        if get_config().code_mode != CodeMode::Off {
+            for language in LANGUAGES {
+                registry.register(CodeParser(language));
+            }
+        }
+        registry
+    }
+
+    /// Adds a parser. Parsers registered later take precedence over earlier ones for the same format.
+    pub fn register(&mut self, parser: impl DocumentParser + 'static) {
+        self.parsers.push(Box::new(parser));
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &dyn DocumentParser> {
+        self.parsers.iter().rev().map(|p| p.as_ref())
+    }
+
+    pub fn by_extension(&self, ext: &str) -> Option<&dyn DocumentParser> {
+        let ext = ext.to_lowercase();
+        self.iter().find(|p| p.extensions().contains(&ext.as_str()))
+    }
+
+    pub fn by_mime_type(&self, mime: &str) -> Option<&dyn DocumentParser> {
+        self.iter().find(|p| p.mime_types().contains(&mime))
+    }
+
+    pub fn sniff(&self, head: &[u8]) -> Option<&dyn DocumentParser> {
+        self.iter().find(|p| p.sniff(head))
+    }
+
+    /// Finds the parser for a file from its first bytes `head`. The MIME type sniffed from magic bytes
+    /// comes first, the extension is a fallback. Files without an extension are given to the parser sniffers.
+    pub fn detect(&self, path: &Path, head: &[u8]) -> Option<&dyn DocumentParser> {
+        let by_extension = path.extension().and_then(|e| e.to_str()).and_then(|ext| self.by_extension(ext));
+
+        if let Some(mime) = sniff_mime_type(path, head) {
+            // Prefer the extension's parser when it handles the sniffed type too, e.g. xhtml with an html doctype.
+            // A UTF-16 byte order mark only tells the encoding, so source code or markdown keeps its parser.
+            if let Some(parser) = by_extension.filter(|p| p.mime_types().contains(&mime.as_str()) || mime == "text/plain") {
+                return Some(parser);
+            }
+            if let Some(parser) = self.by_mime_type(&mime) {
+                return Some(parser);
+            }
+        }
+
+        match path.extension() {
+            Some(_) => by_extension,
+            None => self.sniff(head)
+        }
+    }
+}
+
+impl std::fmt::Debug for ParserRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.parsers.iter().map(|p| p.name())).finish()
+    }
+}
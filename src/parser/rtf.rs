@@ -0,0 +1,276 @@
+use std::path::Path;
+
+use encoding_rs::{Encoding, BIG5, EUC_KR, GBK, SHIFT_JIS, WINDOWS_1252};
+
+use super::{ParsedText, error::ParserError, registry::DocumentParser};
+
+/// Destinations whose text is not part of the document: tables, metadata, pictures,
+/// embedded objects and the instructions of fields, whose results are kept.
+const SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl", "colortbl", "stylesheet", "info", "pict", "object", "objdata", "listtable", "listoverridetable",
+    "rsidtbl", "generator", "xmlnstbl", "themedata", "colorschememapping", "datastore", "latentstyles",
+    "filetbl", "revtbl", "pgdsctbl", "fldinst", "bkmkstart", "bkmkend", "footnote", "annotation"
+];
+
+/// State of a group, which is restored when the group ends.
+#[derive(Clone, Copy)]
+struct Group {
+    skip: bool,
+    /// Number of fallback characters after a `\u` unicode character, set by `\uc`.
+    unicode_skip: usize
+}
+
+pub fn parse_rtf(path: &Path) -> Result<ParsedText, ParserError> {
+    let bytes = std::fs::read(path)?;
+    rtf_to_text(&bytes).map(ParsedText::from)
+}
+
+/// Extracts the text of an RTF document. Characters given as bytes in the code page of the document,
+/// like `\'e9`, are decoded with `\ansicpg`, Windows-1252 by default.
+pub fn rtf_to_text(rtf: &[u8]) -> Result<Vec<char>, ParserError> {
+    if !rtf.starts_with(b"{\\rtf") {
+        return Err(ParserError::corrupt("not an RTF document"));
+    }
+
+    let mut text = Vec::new();
+    let mut encoding = WINDOWS_1252;
+    // Bytes of `\'hh` escapes, decoded together since a character can take several bytes.
+    let mut bytes = Vec::new();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut group = Group { skip: false, unicode_skip: 1 };
+    // Fallback characters that are left to skip after a unicode character.
+    let mut fallback = 0;
+    let mut i = 0;
+
+    while i < rtf.len() {
+        let c = rtf[i];
+        if c != b'\\' || rtf.get(i + 1) != Some(&b'\'') {
+            flush(&mut bytes, encoding, &mut text);
+        }
+
+        match c {
+            b'{' => {
+                groups.push(group);
+                fallback = 0;
+                i += 1;
+            },
+            b'}' => {
+                group = groups.pop().unwrap_or(group);
+                fallback = 0;
+                i += 1;
+            },
+            b'\\' => {
+                let (word, parameter, len) = control_word(&rtf[i + 1..]);
+                i += 1 + len;
+
+                if fallback > 0 && word != "bin" {
+                    fallback -= 1;
+                    continue;
+                }
+
+                match word {
+                    "'" => if let Some(byte) = parameter {
+                        if !group.skip {
+                            bytes.push(byte as u8);
+                        }
+                    },
+                    "*" => group.skip = true,
+                    "bin" => i += parameter.unwrap_or(0).max(0) as usize,
+                    "ansicpg" => encoding = code_page(parameter.unwrap_or(1252)),
+                    "uc" => group.unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+                    "u" => {
+                        if let Some(code) = parameter {
+                            // Code points above 32767 are written as negative numbers.
+                            let code = if code < 0 { code + 65536 } else { code };
+                            if !group.skip {
+                                text.push(char::from_u32(code as u32).unwrap_or(char::REPLACEMENT_CHARACTER));
+                            }
+                        }
+                        fallback = group.unicode_skip;
+                    },
+                    word if SKIPPED_DESTINATIONS.contains(&word) => group.skip = true,
+                    word => if !group.skip {
+                        if let Some(c) = control_char(word) {
+                            text.push(c);
+                        }
+                    }
+                }
+            },
+            b'\r' | b'\n' => i += 1,
+            _ => {
+                if fallback > 0 {
+                    fallback -= 1;
+                }else if !group.skip {
+                    bytes.push(c);
+                    flush(&mut bytes, encoding, &mut text);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    flush(&mut bytes, encoding, &mut text);
+    Ok(text)
+}
+
+/// Reads the control word or symbol after a backslash. Returns the word, its numeric parameter and the
+/// length including the space that ends it. `\'hh` is returned as `'` with the byte as parameter.
+fn control_word(rtf: &[u8]) -> (&str, Option<i32>, usize) {
+    let Some(first) = rtf.first() else {
+        return ("", None, 0);
+    };
+
+    if !first.is_ascii_alphabetic() {
+        if *first == b'\'' {
+            let byte = rtf.get(1..3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            return ("'", byte.map(i32::from), if byte.is_some() { 3 } else { 1 });
+        }
+        let symbol = std::str::from_utf8(&rtf[..1]).unwrap_or("");
+        return (symbol, None, 1);
+    }
+
+    let word_len = rtf.iter().take_while(|b| b.is_ascii_alphabetic()).count();
+    let word = std::str::from_utf8(&rtf[..word_len]).unwrap_or("");
+    let mut len = word_len;
+
+    let sign = usize::from(rtf.get(len) == Some(&b'-'));
+    let digits = rtf[len + sign..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let parameter = if digits > 0 {
+        let parameter = std::str::from_utf8(&rtf[len..len + sign + digits]).ok().and_then(|p| p.parse().ok());
+        len += sign + digits;
+        parameter
+    }else{
+        None
+    };
+
+    if rtf.get(len) == Some(&b' ') {
+        len += 1;
+    }
+
+    (word, parameter, len)
+}
+
+/// Text of control words and symbols that stand for characters. Breaks become spaces.
+fn control_char(word: &str) -> Option<char> {
+    let c = match word {
+        "par" | "line" | "sect" | "page" | "tab" | "cell" | "row" | "emspace" | "enspace" | "~" => ' ',
+        "emdash" => '\u{2014}',
+        "endash" => '\u{2013}',
+        "bullet" => '\u{2022}',
+        "lquote" => '\u{2018}',
+        "rquote" => '\u{2019}',
+        "ldblquote" => '\u{201C}',
+        "rdblquote" => '\u{201D}',
+        "_" => '-',
+        "\\" => '\\',
+        "{" => '{',
+        "}" => '}',
+        _ => return None
+    };
+    Some(c)
+}
+
+fn code_page(code_page: i32) -> &'static Encoding {
+    match code_page {
+        932 => SHIFT_JIS,
+        936 => GBK,
+        949 => EUC_KR,
+        950 => BIG5,
+        10000 => Encoding::for_label(b"macintosh").unwrap_or(WINDOWS_1252),
+        code_page => Encoding::for_label(format!("windows-{code_page}").as_bytes()).unwrap_or(WINDOWS_1252)
+    }
+}
+
+fn flush(bytes: &mut Vec<u8>, encoding: &'static Encoding, text: &mut Vec<char>) {
+    if bytes.is_empty() {
+        return;
+    }
+    text.extend(encoding.decode_without_bom_handling(bytes).0.chars());
+    bytes.clear();
+}
+
+pub struct RtfParser;
+
+impl DocumentParser for RtfParser {
+    fn name(&self) -> &str {
+        "RTF"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rtf"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/rtf", "text/rtf"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        parse_rtf(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(rtf: &str) -> String {
+        rtf_to_text(rtf.as_bytes()).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn reads_control_words() {
+        assert_eq!(control_word(b"par Hello"), ("par", None, 4));
+        assert_eq!(control_word(b"fs24\\b"), ("fs", Some(24), 4));
+        assert_eq!(control_word(b"u-3913 ?"), ("u", Some(-3913), 7));
+        assert_eq!(control_word(b"'e9t"), ("'", Some(0xe9), 3));
+        assert_eq!(control_word(b"'zz"), ("'", None, 1));
+        assert_eq!(control_word(b"{"), ("{", None, 1));
+        assert_eq!(control_word(b""), ("", None, 0));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(rtf_to_text(b"plain text").is_err());
+    }
+
+    #[test]
+    fn skips_tables_and_starred_destinations() {
+        let rtf = r"{\rtf1\ansi{\fonttbl{\f0 Times New Roman;}}{\colortbl;\red0;}{\*\generator Writer;}{\info{\title Secret}}\f0 Hello\par World}";
+        assert_eq!(text(rtf), "Hello World");
+    }
+
+    #[test]
+    fn decodes_hex_escapes_with_code_page() {
+        assert_eq!(text(r"{\rtf1\ansi caf\'e9}"), "café");
+        assert_eq!(text(r"{\rtf1\ansi\ansicpg1251 \'cf\'f0\'e8}"), "При");
+        assert_eq!(text(r"{\rtf1\ansi\ansicpg932 \'93\'fa\'96\'7b}"), "日本");
+    }
+
+    #[test]
+    fn skips_fallback_of_unicode_characters() {
+        assert_eq!(text(r"{\rtf1 \u8364?5}"), "€5");
+        assert_eq!(text(r"{\rtf1\uc2 \u8364\'80\'805}"), "€5");
+        assert_eq!(text(r"{\rtf1 \u-3913?}"), "\u{F0B7}");
+        // The fallback count is restored at the end of a group.
+        assert_eq!(text(r"{\rtf1 {\uc0 \u8364}\u8364?x}"), "€€x");
+    }
+
+    #[test]
+    fn keeps_field_results() {
+        let rtf = r#"{\rtf1 see {\field{\*\fldinst HYPERLINK "https://example.com"}{\fldrslt the site}}}"#;
+        assert_eq!(text(rtf), "see the site");
+    }
+
+    #[test]
+    fn translates_symbols() {
+        assert_eq!(text(r"{\rtf1 a\tab b\emdash c\{d\}\\}"), "a b\u{2014}c{d}\\");
+        assert_eq!(text(r"{\rtf1 \ldblquote hi\rdblquote }"), "\u{201C}hi\u{201D}");
+    }
+
+    #[test]
+    fn skips_binary_data() {
+        assert_eq!(text("{\\rtf1 a{\\*\\blipuid x}\\bin3 }{}b}"), "ab");
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fs::File, path::Path, io::Read};
+
+use xml::{EventReader, reader::XmlEvent};
+use zip::ZipArchive;
+
+/// Number of bytes read from the start of a file to guess its contents.
+pub const SNIFF_LEN: usize = 8192;
+
+pub fn read_head(path: &Path) -> std::io::Result<Vec<u8>> {
+    let file = std::fs::File::open(path)?;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    file.take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Guesses if the bytes are binary data instead of text.
+/// Any NUL byte, or more than 10% control characters, counts as binary.
+pub fn is_binary(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return true;
+    }
+
+    let control = head.iter()
+        .filter(|b| b.is_ascii_control() && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+
+    control * 10 > head.len()
+}
+
+/// Checks if the text in `head` starts with `prefix`, ignoring ASCII case,
+/// a UTF-8 byte order mark and leading whitespace.
+pub fn text_starts_with(head: &[u8], prefix: &str) -> bool {
+    let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = head.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(head.len());
+    let head = &head[start..];
+
+    head.len() >= prefix.len() && head[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+/// Guesses the MIME type of a file from its magic bytes, independent of its extension.
+/// Zip files are opened to tell office documents apart from plain archives.
+pub fn sniff_mime_type(path: &Path, head: &[u8]) -> Option<String> {
+    if head.starts_with(b"%PDF-") {
+        return Some("application/pdf".to_string());
+    }
+
+    if head.starts_with(b"PK\x03\x04") {
+        return Some(zip_mime_type(path).unwrap_or_else(|| "application/zip".to_string()));
+    }
+
+    // Word saves RTF documents with a `.doc` extension too.
+    if head.starts_with(b"{\\rtf") {
+        return Some("application/rtf".to_string());
+    }
+
+    if head.starts_with(b"\xFF\xFE") || head.starts_with(b"\xFE\xFF") {
+        return Some("text/plain".to_string());
+    }
+
+    if text_starts_with(head, "<!doctype html") {
+        return Some("text/html".to_string());
+    }
+
+    None
+}
+
+/// MIME type of a zip based document: the `mimetype` entry of OpenDocument and EPUB files,
+/// or the type of the main part in `[Content_Types].xml` of Office Open XML files.
+fn zip_mime_type(path: &Path) -> Option<String> {
+    let mut zip = ZipArchive::new(File::open(path).ok()?).ok()?;
+
+    if let Ok(mut entry) = zip.by_name("mimetype") {
+        let mut mime = String::new();
+        entry.read_to_string(&mut mime).ok()?;
+        return Some(mime.trim().to_string());
+    }
+
+    let types = zip.by_name("[Content_Types].xml").ok()?;
+    for event in EventReader::new(types) {
+        match event {
+            Ok(XmlEvent::StartElement { attributes, .. }) => {
+                let main = attributes.iter()
+                    .filter(|a| a.name.local_name == "ContentType")
+                    .find_map(|a| a.value.strip_suffix(".main+xml"));
+                if let Some(mime) = main {
+                    return Some(mime.to_string());
+                }
+            },
+            Err(_) => return None,
+            _ => ()
+        }
+    }
+
+    None
+}
@@ -1,16 +1,154 @@
-use std::{path::Path, io::{BufReader, Read}};
+use std::path::Path;
 
-use super::error::ParserError;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+use super::{ParsedText, error::ParserError, registry::DocumentParser, sniff::{is_binary, SNIFF_LEN}};
 
 pub fn parse_txt(file: &Path) -> Result<Vec<char>, ParserError> {
-    let mut result: Vec<u8> = Vec::new();
-    let file = std::fs::File::open(file)?;
-    let mut reader = BufReader::new(file);
-    reader.read_to_end(&mut result)?;
-    let result = String::from_utf8_lossy(&result);
+    let bytes = std::fs::read(file)?;
+
+    Ok(decode_text(&bytes)
+        .chars()
+        .filter(|c| *c != '\r')
+        .map(|c| if c == '\n' { ' ' } else { c })
+        .collect())
+}
+
+/// Decodes text in the encoding of its byte order mark. Text without one is read as UTF-16 when every
+/// other byte is zero, as UTF-8 when it is mostly valid UTF-8, and as Windows-1252 otherwise,
+/// which is a superset of Latin-1.
+pub(super) fn decode_text(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_len..]).0.into_owned();
+    }
+
+    // Checked first, as UTF-16 of ASCII text is valid UTF-8 with a zero byte after every character.
+    if let Some(encoding) = guess_utf16(bytes) {
+        return encoding.decode_without_bom_handling(bytes).0.into_owned();
+    }
+
+    let (utf8, had_errors) = UTF_8.decode_without_bom_handling(bytes);
+    if !had_errors {
+        return utf8.into_owned();
+    }
+
+    // UTF-8 with a few stray bytes has more valid multibyte characters than invalid sequences.
+    let invalid = utf8.chars().filter(|c| *c == char::REPLACEMENT_CHARACTER).count();
+    let multibyte = utf8.chars().filter(|c| !c.is_ascii() && *c != char::REPLACEMENT_CHARACTER).count();
+    if multibyte > invalid * 10 {
+        return utf8.into_owned();
+    }
+
+    WINDOWS_1252.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Whether the text is UTF-16 by its byte order mark or its zero bytes, which makes it look binary.
+pub(super) fn is_utf16(bytes: &[u8]) -> bool {
+    match Encoding::for_bom(bytes) {
+        Some((encoding, _)) => encoding == UTF_16LE || encoding == UTF_16BE,
+        None => guess_utf16(bytes).is_some()
+    }
+}
+
+/// Guesses UTF-16 without a byte order mark by the zero bytes latin text has in every other byte.
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+
+    let zeros = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|b| **b == 0).count();
+    let (even, odd) = (zeros(0), zeros(1));
+
+    if odd * 2 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    }else if even * 2 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    }else{
+        None
+    }
+}
+
+pub struct TxtParser;
+
+impl DocumentParser for TxtParser {
+    fn name(&self) -> &str {
+        "Plain text"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["text/plain"]
+    }
+
+    fn sniff(&self, head: &[u8]) -> bool {
+        !is_binary(head)
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        parse_txt(path).map(ParsedText::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| if big_endian { u.to_be_bytes() } else { u.to_le_bytes() }).collect()
+    }
+
+    #[test]
+    fn reads_utf8() {
+        assert_eq!(decode_text("naïve café".as_bytes()), "naïve café");
+        assert_eq!(decode_text(b""), "");
+    }
+
+    #[test]
+    fn strips_byte_order_marks() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBFhello"), "hello");
+
+        let mut le = vec![0xFF, 0xFE];
+        le.extend(utf16("Grüße", false));
+        assert_eq!(decode_text(&le), "Grüße");
+
+        let mut be = vec![0xFE, 0xFF];
+        be.extend(utf16("Grüße", true));
+        assert_eq!(decode_text(&be), "Grüße");
+    }
+
+    #[test]
+    fn guesses_utf16_without_byte_order_mark() {
+        assert_eq!(decode_text(&utf16("plain ascii notes", false)), "plain ascii notes");
+        assert_eq!(decode_text(&utf16("plain ascii notes", true)), "plain ascii notes");
+        assert_eq!(decode_text(&utf16("Grüße aus Köln", false)), "Grüße aus Köln");
+    }
+
+    #[test]
+    fn tells_utf16_apart_from_binary() {
+        let mut bom = vec![0xFF, 0xFE];
+        bom.extend(utf16("class Program {}", false));
+        assert!(is_utf16(&bom));
+        assert!(is_utf16(&utf16("class Program {}", true)));
+        assert!(!is_utf16(b"class Program {}"));
+        assert!(!is_utf16(&[0x47, 0x40, 0x11, 0x10, 0x00, 0x42, 0xF0, 0x25]));
+    }
 
-    
+    #[test]
+    fn reads_latin1_as_windows_1252() {
+        assert_eq!(decode_text(b"caf\xE9 cr\xE8me \x80 5"), "café crème € 5");
+    }
 
-    //Ok(result.replace('\r', "").replace('\n', " "))
-    Ok(result.replace('\r', "").replace('\n', " ").chars().collect())
+    #[test]
+    fn keeps_utf8_with_stray_bytes() {
+        let mut bytes = "Überall Grüße, schöne Äpfel und süße Öfen in Köln. ".repeat(3).into_bytes();
+        bytes.push(0xE9);
+        let text = decode_text(&bytes);
+        assert!(text.starts_with("Überall Grüße"));
+        assert!(text.ends_with(char::REPLACEMENT_CHARACTER));
+    }
 }
@@ -0,0 +1,235 @@
+use std::{path::Path, fs::File, io::{Read, Seek}};
+
+use xml::{EventReader, reader::XmlEvent};
+use zip::{ZipArchive, result::ZipError};
+
+use super::{ParsedText, Section, error::ParserError, registry::DocumentParser, docx::zip_relationships};
+
+const WORKBOOK: &str = "xl/workbook.xml";
+
+/// Extracts the text of the cells of every sheet, in the order of the workbook.
+/// Every sheet becomes a section named after it. Sheets that can't be read become warnings.
+pub fn get_xlsx(path: &Path) -> Result<ParsedText, ParserError> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let mut parsed = xlsx_to_text(&mut zip)?;
+    parsed.warnings = parsed.warnings.into_iter().map(|w| w.with_path(path)).collect();
+    Ok(parsed)
+}
+
+fn xlsx_to_text<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<ParsedText, ParserError> {
+    let mut parsed = ParsedText::default();
+
+    let rels = zip_relationships(WORKBOOK, zip)?;
+    // Without the shared strings the numbers and inline strings of the sheets are still read.
+    let shared_strings = match rels.values().find(|r| r.kind.ends_with("/sharedStrings")) {
+        Some(rel) => read_shared_strings(&rel.target, zip).unwrap_or_else(|e| {
+            parsed.warn(e);
+            Vec::new()
+        }),
+        None => Vec::new()
+    };
+
+    let workbook = zip.by_name(WORKBOOK).map_err(|e| ParserError::from(e).within(WORKBOOK))?;
+    let sheets = read_sheets(workbook).map_err(|e| e.within(WORKBOOK))?;
+
+    for (sheet, id) in sheets {
+        let Some(rel) = rels.get(&id) else {
+            continue;
+        };
+
+        if !parsed.text.is_empty() {
+            parsed.text.push(' ');
+        }
+        parsed.sections.push(Section { title: sheet, anchor: None, level: None, start: parsed.text.len() });
+
+        let cells = match zip.by_name(&rel.target) {
+            Ok(part) => read_cells(part, &shared_strings),
+            Err(e) => Err(ParserError::from(e))
+        };
+        match cells {
+            Ok(text) => parsed.text.extend(text.chars()),
+            Err(e) => parsed.warn(e.within(rel.target.as_str()))
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Names of the sheets with the ids of their relationships.
+fn read_sheets(workbook: impl Read) -> Result<Vec<(String, String)>, ParserError> {
+    let mut sheets = Vec::new();
+
+    for event in EventReader::new(workbook) {
+        if let XmlEvent::StartElement { name, attributes, .. } = event? {
+            if name.local_name != "sheet" {
+                continue;
+            }
+
+            let name = attributes.iter().find(|a| a.name.local_name == "name" && a.name.namespace.is_none());
+            let id = attributes.iter().find(|a| a.name.local_name == "id" && a.name.namespace.as_deref().is_some_and(|ns| ns.ends_with("relationships")));
+            if let (Some(name), Some(id)) = (name, id) {
+                sheets.push((name.value.clone(), id.value.clone()));
+            }
+        }
+    }
+
+    Ok(sheets)
+}
+
+/// The shared string table, which cells of type `s` refer to by index. Rich text runs are joined.
+fn read_shared_strings<R: Read + Seek>(name: &str, zip: &mut ZipArchive<R>) -> Result<Vec<String>, ParserError> {
+    let part = match zip.by_name(name) {
+        Ok(part) => part,
+        Err(ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(ParserError::from(e).within(name))
+    };
+
+    let mut strings = Vec::new();
+    let mut in_text = false;
+    // Phonetic hints of east asian text repeat the string, so they are skipped.
+    let mut in_phonetic = false;
+
+    for event in EventReader::new(part) {
+        match event.map_err(|e| ParserError::from(e).within(name))? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "si" => strings.push(String::new()),
+                "t" => in_text = true,
+                "rPh" => in_phonetic = true,
+                _ => ()
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "t" => in_text = false,
+                "rPh" => in_phonetic = false,
+                _ => ()
+            },
+            XmlEvent::Characters(chars) | XmlEvent::Whitespace(chars) if in_text && !in_phonetic => {
+                if let Some(string) = strings.last_mut() {
+                    string.push_str(&chars);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    Ok(strings)
+}
+
+/// Text of the cells of a sheet. Shared strings are looked up, numbers are kept as they are stored.
+fn read_cells(sheet: impl Read, shared_strings: &[String]) -> Result<String, ParserError> {
+    let mut text = String::new();
+    let mut cell_type = None;
+    let mut in_value = false;
+
+    for event in EventReader::new(sheet) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => match name.local_name.as_str() {
+                "c" => cell_type = attributes.iter().find(|a| a.name.local_name == "t").map(|a| a.value.clone()),
+                // `v` holds the value, `t` the text of an inline string. Formulas in `f` are left out.
+                "v" | "t" => in_value = true,
+                _ => ()
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "v" | "t" => {
+                    in_value = false;
+                    text.push(' ');
+                },
+                _ => ()
+            },
+            XmlEvent::Characters(chars) if in_value => {
+                match cell_type.as_deref() {
+                    Some("s") => {
+                        if let Some(string) = chars.trim().parse::<usize>().ok().and_then(|i| shared_strings.get(i)) {
+                            text.push_str(string);
+                        }
+                    },
+                    // Booleans and errors are no text.
+                    Some("b") | Some("e") => (),
+                    _ => text.push_str(&chars)
+                }
+            },
+            _ => ()
+        }
+    }
+
+    Ok(text)
+}
+
+pub struct XlsxParser;
+
+impl DocumentParser for XlsxParser {
+    fn name(&self) -> &str {
+        "XLSX"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xlsx"]
+    }
+
+    fn mime_types(&self) -> &[&str] {
+        &["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"]
+    }
+
+    fn parse(&self, path: &Path) -> Result<ParsedText, ParserError> {
+        get_xlsx(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::{ZipWriter, write::FileOptions};
+
+    const REL: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+    const MAIN: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+
+    fn archive(parts: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn workbook(shared_strings: &str, sheet: &str) -> ZipArchive<Cursor<Vec<u8>>> {
+        let workbook = format!(r#"<workbook xmlns="{MAIN}" xmlns:r="{REL}"><sheets><sheet name="Costs" sheetId="1" r:id="rId1"/></sheets></workbook>"#);
+        let rels = format!(r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="{REL}/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="{REL}/sharedStrings" Target="sharedStrings.xml"/></Relationships>"#);
+        archive(&[
+            (WORKBOOK, &workbook),
+            ("xl/_rels/workbook.xml.rels", &rels),
+            ("xl/sharedStrings.xml", shared_strings),
+            ("xl/worksheets/sheet1.xml", &format!(r#"<worksheet xmlns="{MAIN}"><sheetData><row r="1">{sheet}</row></sheetData></worksheet>"#))
+        ])
+    }
+
+    fn text(parsed: &ParsedText) -> String {
+        parsed.text.iter().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn looks_up_shared_strings() {
+        let shared = format!(r#"<sst xmlns="{MAIN}"><si><t>plain</t></si><si><r><t>rich </t></r><r><t>text</t></r><rPh><t>hint</t></rPh></si></sst>"#);
+        let mut zip = workbook(&shared, r#"<c r="A1" t="s"><v>1</v></c><c r="B1" t="s"><v>0</v></c><c r="C1" t="s"><v>9</v></c>"#);
+        let parsed = xlsx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "rich text plain");
+        assert_eq!(parsed.sections[0].title, "Costs");
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn reads_inline_strings_and_numbers_but_not_booleans_or_formulas() {
+        let sheet = r#"<c r="A1" t="inlineStr"><is><t>inline</t></is></c><c r="B1"><f>SUM(C1)</f><v>42</v></c><c r="C1" t="b"><v>1</v></c><c r="D1" t="e"><v>#DIV/0!</v></c>"#;
+        let mut zip = workbook(&format!(r#"<sst xmlns="{MAIN}"/>"#), sheet);
+        let parsed = xlsx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "inline 42");
+    }
+
+    #[test]
+    fn broken_shared_strings_become_a_warning() {
+        let mut zip = workbook("<sst><si>", r#"<c r="A1" t="s"><v>0</v></c><c r="B1"><v>7</v></c>"#);
+        let parsed = xlsx_to_text(&mut zip).unwrap();
+        assert_eq!(text(&parsed), "7");
+        assert_eq!(parsed.warnings.len(), 1);
+    }
+}
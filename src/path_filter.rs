@@ -0,0 +1,264 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::{Arc, OnceLock, RwLock}};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{gitignore::{Gitignore, GitignoreBuilder}, Match};
+
+use crate::config::Config;
+
+pub static GLOB_FILTER: OnceLock<PathFilter> = OnceLock::new();
+
+/// Ignore files that are read from every directory, in order of increasing precedence.
+pub const IGNORE_FILE_NAMES: [&str; 3] = [".gitignore", ".ignore", ".ksignore"];
+
+/// Decides which files and directories below `root` are skipped during indexing.
+/// A path is skipped when it is a dotfile, is excluded by one of the ignore files or
+/// by the exclude globs of the config, or (files only) does not match any include glob.
+#[derive(Debug)]
+pub struct PathFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore_files: RwLock<HashMap<PathBuf, Arc<Gitignore>>>,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path, config: &Config) -> Self {
+        let include = if config.include.is_empty() {
+            None
+        }else{
+            Some(build_globset(&config.include))
+        };
+
+        PathFilter {
+            root: root.to_path_buf(),
+            include,
+            exclude: build_globset(&config.exclude),
+            ignore_files: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Checks only the entry itself. The caller has to make sure none of its parent
+    /// directories are ignored, which is the case when walking the tree top down.
+    pub fn is_entry_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dot_file(path) {
+            return true;
+        }
+
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if self.exclude.is_match(relative) {
+            return true;
+        }
+
+        if !is_dir {
+            if let Some(include) = &self.include {
+                if !include.is_match(relative) {
+                    return true;
+                }
+            }
+        }
+
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+
+            match self.ignore_file_for(dir).matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => ()
+            }
+        }
+
+        false
+    }
+
+    /// Checks the path and all of its parent directories up to the root.
+    /// Used for paths that are not reached through the walker, like watcher events.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return is_dot_file(path);
+        };
+
+        let mut current = self.root.clone();
+        let mut components = relative.components().peekable();
+
+        while let Some(component) = components.next() {
+            current.push(component);
+            let last = components.peek().is_none();
+
+            if self.is_entry_ignored(&current, !last || is_dir) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Drops the cached ignore rules of `dir`, so they are read again on the next lookup.
+    pub fn invalidate(&self, dir: &Path) {
+        self.ignore_files.write().unwrap().remove(dir);
+    }
+
+    fn ignore_file_for(&self, dir: &Path) -> Arc<Gitignore> {
+        if let Some(gitignore) = self.ignore_files.read().unwrap().get(dir) {
+            return gitignore.clone();
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        for name in IGNORE_FILE_NAMES {
+            let file = dir.join(name);
+            if !file.is_file() {
+                continue;
+            }
+
+            if let Some(e) = builder.add(&file) {
+                tracing::error!("Could not read ignore file {}: {e}", file.display());
+            }
+        }
+
+        let gitignore = Arc::new(builder.build().unwrap_or_else(|e| {
+            tracing::error!("Invalid ignore rules in {}: {e}", dir.display());
+            Gitignore::empty()
+        }));
+
+        self.ignore_files.write().unwrap().insert(dir.to_path_buf(), gitignore.clone());
+        gitignore
+    }
+}
+
+pub fn is_dot_file(path: &Path) -> bool {
+    path.file_name().and_then(|s| s.to_str()).map(|s|s.starts_with('.')).unwrap_or(false)
+}
+
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name().and_then(|s| s.to_str()).map(|s| IGNORE_FILE_NAMES.contains(&s)).unwrap_or(false)
+}
+
+fn build_globset(globs: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        match Glob::new(glob) {
+            Ok(g) => {
+                builder.add(g);
+            },
+            Err(e) => tracing::error!("Invalid glob {glob} in config: {e}")
+        }
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        tracing::error!("Could not build glob set: {e}");
+        GlobSet::empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn filter(root: &Path, include: &[&str], exclude: &[&str]) -> PathFilter {
+        let config = Config {
+            include: include.iter().map(|g| g.to_string()).collect(),
+            exclude: exclude.iter().map(|g| g.to_string()).collect(),
+            ..Config::default()
+        };
+        PathFilter::new(root, &config)
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let filter = filter(root, &["**/*.md"], &["drafts/**", "old"]);
+
+        assert!(!filter.is_ignored(&root.join("notes/a.md"), false));
+        assert!(filter.is_ignored(&root.join("notes/a.txt"), false));
+        assert!(filter.is_ignored(&root.join("drafts/a.md"), false));
+        assert!(filter.is_ignored(&root.join("old"), true));
+        assert!(filter.is_ignored(&root.join("old/a.md"), false));
+    }
+
+    #[test]
+    fn include_globs_apply_to_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let filter = filter(root, &["*.md", "**/*.md"], &[]);
+
+        assert!(!filter.is_entry_ignored(&root.join("notes"), true));
+        assert!(!filter.is_ignored(&root.join("notes/deep/a.md"), false));
+        assert!(!filter.is_ignored(&root.join("a.md"), false));
+    }
+
+    #[test]
+    fn dotfiles_are_ignored_but_not_a_dotted_root() {
+        let dir = tempfile::Builder::new().prefix(".root").tempdir().unwrap();
+        let root = dir.path();
+        let filter = filter(root, &[], &[]);
+
+        assert!(filter.is_ignored(&root.join(".git/config"), false));
+        assert!(filter.is_ignored(&root.join("notes/.hidden"), false));
+        assert!(!filter.is_ignored(&root.join("notes/a.md"), false));
+    }
+
+    #[test]
+    fn nested_ignore_files_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join(".gitignore"), "*.log\nbuild/\n");
+        write(&root.join("logs/.gitignore"), "!keep.log\n");
+        write(&root.join("logs/deep/.ksignore"), "keep.log\n");
+        let filter = filter(root, &[], &[]);
+
+        assert!(filter.is_ignored(&root.join("a.log"), false));
+        assert!(filter.is_ignored(&root.join("build/out.txt"), false));
+        assert!(filter.is_ignored(&root.join("logs/other.log"), false));
+        assert!(!filter.is_ignored(&root.join("logs/keep.log"), false));
+        assert!(filter.is_ignored(&root.join("logs/deep/keep.log"), false));
+        assert!(!filter.is_ignored(&root.join("logs/notes.txt"), false));
+    }
+
+    #[test]
+    fn later_ignore_file_names_take_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(&root.join(".gitignore"), "*.txt\n");
+        write(&root.join(".ksignore"), "!readme.txt\n");
+        let filter = filter(root, &[], &[]);
+
+        assert!(filter.is_ignored(&root.join("a.txt"), false));
+        assert!(!filter.is_ignored(&root.join("readme.txt"), false));
+    }
+
+    #[test]
+    fn invalidate_reads_changed_ignore_files_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let sub = root.join("sub");
+        write(&sub.join(".ignore"), "a.txt\n");
+        let filter = filter(root, &[], &[]);
+
+        assert!(filter.is_ignored(&sub.join("a.txt"), false));
+
+        write(&sub.join(".ignore"), "b.txt\n");
+        assert!(filter.is_ignored(&sub.join("a.txt"), false), "rules are cached until invalidated");
+
+        filter.invalidate(&sub);
+        assert!(!filter.is_ignored(&sub.join("a.txt"), false));
+        assert!(filter.is_ignored(&sub.join("b.txt"), false));
+    }
+
+    #[test]
+    fn paths_outside_the_root_only_check_dotfiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = filter(&dir.path().join("root"), &["*.md"], &[]);
+
+        assert!(!filter.is_ignored(Path::new("/elsewhere/a.txt"), false));
+        assert!(filter.is_ignored(Path::new("/elsewhere/.a"), false));
+    }
+}
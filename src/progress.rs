@@ -0,0 +1,124 @@
+use std::{fmt::Display, path::PathBuf, sync::mpsc::{self, Receiver, Sender}};
+
+use crate::model::base::SkipReason;
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// A supported file was found by the walker or the watcher.
+    Discovered(PathBuf),
+    /// The file is already up to date in the index.
+    Unchanged(PathBuf),
+    /// The file was parsed. Parts of it that could not be read are counted in `warnings`.
+    Parsed { path: PathBuf, bytes: u64, warnings: usize },
+    Skipped { path: PathBuf, reason: SkipReason },
+    Failed { path: PathBuf, error: String },
+    /// A full directory scan has completed.
+    Finished
+}
+
+/// Sending half of the progress event stream. Reporting is a no-op when nobody listens.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    sender: Option<Sender<ProgressEvent>>
+}
+
+impl ProgressReporter {
+    pub fn new() -> (Self, Receiver<ProgressEvent>) {
+        let (tx, rx) = mpsc::channel();
+        (ProgressReporter { sender: Some(tx) }, rx)
+    }
+
+    pub fn none() -> Self {
+        ProgressReporter { sender: None }
+    }
+
+    pub fn report(&self, event: ProgressEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+/// Running totals of the progress events.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressStats {
+    pub discovered: usize,
+    pub unchanged: usize,
+    pub parsed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub warnings: usize,
+    pub bytes: u64,
+    pub finished: bool
+}
+
+impl ProgressStats {
+    pub fn apply(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::Discovered(_) => {
+                self.discovered += 1;
+                self.finished = false;
+            },
+            ProgressEvent::Unchanged(_) => self.unchanged += 1,
+            ProgressEvent::Parsed { bytes, warnings, .. } => {
+                self.parsed += 1;
+                self.warnings += warnings;
+                self.bytes += bytes;
+            },
+            ProgressEvent::Skipped { .. } => self.skipped += 1,
+            ProgressEvent::Failed { .. } => self.failed += 1,
+            ProgressEvent::Finished => self.finished = true,
+        }
+    }
+
+    /// Applies every event that is currently waiting in the receiver.
+    pub fn drain(&mut self, rx: &Receiver<ProgressEvent>) {
+        while let Ok(event) = rx.try_recv() {
+            self.apply(&event);
+        }
+    }
+
+    pub fn processed(&self) -> usize {
+        self.unchanged + self.parsed + self.skipped + self.failed
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.processed() >= self.discovered
+    }
+
+    /// Renders a text progress bar of `width` cells followed by the totals.
+    pub fn bar(&self, width: usize) -> String {
+        let filled = (self.processed() * width).checked_div(self.discovered).unwrap_or(0).min(width);
+
+        format!("[{}{}] {self}", "=".repeat(filled), " ".repeat(width - filled))
+    }
+}
+
+impl Display for ProgressStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} files, {} parsed, {} skipped, {} failed, {}",
+            self.processed(), self.discovered, self.parsed, self.skipped, self.failed, format_bytes(self.bytes))?;
+
+        if self.warnings > 0 {
+            write!(f, ", {} warnings", self.warnings)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    }else{
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -1,11 +1,11 @@
-use std::{io::{stdout, Result}, path::PathBuf, fmt::Display};
+use std::{io::{stdout, Result}, path::PathBuf, fmt::Display, time::{Duration, Instant}};
 
 use crossterm::{ExecutableCommand, terminal::{EnterAlternateScreen, enable_raw_mode, LeaveAlternateScreen, disable_raw_mode}, event::{self, KeyEventKind, KeyCode, KeyModifiers, KeyEvent, Event}};
 use ratatui::{Terminal, prelude::{CrosstermBackend, Stylize, Layout, Direction, Constraint}, widgets::{Paragraph, List, ListDirection, ListState}, style::{Modifier, Style, Color}, Frame,};
 
-use crate::model::base::Model;
+use crate::model::base::{Model, Query, SkipReason};
 
-use crate::{model::GLOB_CORPUS, indexer::IndexerTask};
+use crate::{model::GLOB_CORPUS, indexer::IndexerTask, progress::ProgressStats, duplicates::{SearchHit, collapse_duplicates}, parser::archive::extract_document};
 
 #[derive(PartialEq, Eq)]
 pub enum UserMode {
@@ -25,29 +25,90 @@ impl Display for UserMode {
 }
 
 struct App {
-    search_results: Option<Vec<PathBuf>>,
+    search_results: Option<Vec<SearchHit>>,
     query_input: String,
     query_send: String,
     user_mode: UserMode,
     result_list_state: ListState,
+    progress: ProgressStats,
+    /// Number of parsed documents when the results were last computed.
+    results_parsed: usize,
+    results_refreshed: Instant,
+    /// Lines of the failed files panel, `None` while the panel is hidden.
+    failures: Option<Vec<String>>,
+    /// Number of failed files when the panel was last loaded.
+    failures_seen: usize,
 
 }
 
+/// Minimum time between refreshing the results of the last query while indexing.
+const RESULT_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
 impl App {
     pub fn new() -> Self {
-        App { search_results: None, query_input: "".to_string(), user_mode: UserMode::Normal, result_list_state: ListState::default(), query_send: "".into() }
+        App { search_results: None, query_input: "".to_string(), user_mode: UserMode::Normal, result_list_state: ListState::default(), query_send: "".into(), progress: ProgressStats::default(), results_parsed: 0, results_refreshed: Instant::now(), failures: None, failures_seen: 0 }
+    }
+
+    fn load_failures(&mut self) {
+        self.failures_seen = self.progress.failed;
+
+        let mut failures: Vec<(PathBuf, SkipReason)> = GLOB_CORPUS.get().unwrap().read().unwrap().get_skipped().into_iter()
+            .filter(|(_, reason)| matches!(reason, SkipReason::Failed { .. }))
+            .collect();
+        failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        self.failures = Some(failures.iter().map(|(path, reason)| format!("{}: {reason}", path.display())).collect());
+    }
+
+    pub fn toggle_failures(&mut self) {
+        if self.failures.is_some() {
+            self.failures = None;
+        }else{
+            self.load_failures();
+        }
+    }
+
+    fn run_query(&mut self, query: &str) -> Vec<SearchHit> {
+        self.results_parsed = self.progress.parsed;
+        self.results_refreshed = Instant::now();
+
+        let query = Query::parse(query);
+        let model = GLOB_CORPUS.get().unwrap().read().unwrap();
+        let results = query.run(&*model);
+
+        collapse_duplicates(results, &*model).into_iter().take(5)
+            .map(|mut hit| {
+                hit.section = query.section_of(&*model, &hit.path);
+                hit
+            })
+            .collect()
+    }
+
+    /// Runs the last query again when documents were added since its results were computed.
+    pub fn refresh_results(&mut self) {
+        if self.failures.is_some() && self.progress.failed != self.failures_seen {
+            self.load_failures();
+        }
+
+        if self.query_send.is_empty() || self.progress.parsed == self.results_parsed || self.results_refreshed.elapsed() < RESULT_REFRESH_INTERVAL {
+            return;
+        }
+
+        let results = self.run_query(&self.query_send.clone());
+        if results.is_empty() {
+            self.search_results = None;
+            return;
+        }
+
+        let selected = self.result_list_state.selected().unwrap_or(0).min(results.len() - 1);
+        self.result_list_state.select(Some(selected));
+        self.search_results = Some(results);
     }
 
     pub fn search(&mut self){
+        self.failures = None;
         self.query_send = self.query_input.clone();
-        let results: Vec<PathBuf> = if self.query_input.len() > 2 && self.query_input.starts_with('"') && self.query_input.ends_with('"') {
-            //execute phrase query
-            let actual_query = self.query_input.replace('"', "").chars().collect::<Vec<char>>();
-            GLOB_CORPUS.get().unwrap().read().unwrap().search_phrase(&actual_query).iter().take(5).map(|m| m.0.clone()).collect()
-        }else{
-            let actual_query = self.query_input.replace('"', "").chars().collect::<Vec<char>>();
-            GLOB_CORPUS.get().unwrap().read().unwrap().search_simple(&actual_query).iter().take(5).map(|m| m.0.clone()).collect()
-        };
+        let results = self.run_query(&self.query_input.clone());
 
         if results.is_empty(){
             self.search_results = None;
@@ -63,6 +124,8 @@ impl App {
 
 }
 
+/// Splits `in:<section>` off the start of a query, e.g. `in:owner alice` or `in:"due date" 2024`,
+/// which limits the results to documents whose section of that title contains the query.
 pub fn tui(indexer: &mut IndexerTask) -> Result<()> {
     init_panic_handler();
     
@@ -73,11 +136,14 @@ pub fn tui(indexer: &mut IndexerTask) -> Result<()> {
 
     terminal.clear()?;
     let mut app = App::new();
-    let poller = indexer.create_watcher().unwrap();
+    let progress_rx = indexer.take_progress();
 
     loop {
+        if let Some(rx) = &progress_rx {
+            app.progress.drain(rx);
+        }
+        app.refresh_results();
         terminal.draw(|frame| ui(frame, &mut app))?;
-        poller.poll().unwrap();
          
         if event::poll(std::time::Duration::from_millis(16))? && handle_event(&mut app, event::read()?){
             break;
@@ -124,9 +190,10 @@ fn ui(frame: &mut Frame, app: &mut App){
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![
-            Constraint::Percentage(96),
-            Constraint::Percentage(2),
-            Constraint::Percentage(2)
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1)
 
         ])
         .split(frame.size());
@@ -134,8 +201,21 @@ fn ui(frame: &mut Frame, app: &mut App){
     frame.render_widget(Paragraph::new(format!("Query: {}", app.query_input)).white().on_black(), layout[1]);
     frame.render_widget(Paragraph::new(format!("Mode: {}", app.user_mode)).white().on_black(), layout[2]);
 
-    if let Some(results) = &app.search_results {
-        let list = List::new(results.clone().iter().map(|r| r.display().to_string().replace('\n', "")).collect::<Vec<String>>())
+    let status = if app.progress.is_idle() {
+        format!("Index up to date: {}", app.progress)
+    }else{
+        format!("Indexing: {}", app.progress.bar(20))
+    };
+    frame.render_widget(Paragraph::new(status).white().on_black(), layout[3]);
+
+    if let Some(failures) = &app.failures {
+        if failures.is_empty() {
+            frame.render_widget(Paragraph::new("No failed files").white().on_black(), layout[0]);
+        }else{
+            frame.render_widget(List::new(failures.iter().map(|f| f.replace('\n', " ")).collect::<Vec<String>>()).style(Style::default().fg(Color::Red)), layout[0]);
+        }
+    }else if let Some(results) = &app.search_results {
+        let list = List::new(results.iter().map(|r| r.to_string().replace('\n', "")).collect::<Vec<String>>())
             .style(Style::default().fg(Color::White))
             .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
             .highlight_symbol(">>")
@@ -173,6 +253,9 @@ fn handle_normal_mode_inputs(app: &mut App, key: KeyEvent) -> bool {
     if key.code == KeyCode::Char('k'){
         app.user_mode = UserMode::ResultBrowsing;
     }
+    if key.code == KeyCode::Char('f'){
+        app.toggle_failures();
+    }
     false
 
 }
@@ -244,6 +327,10 @@ fn handle_result_mode_inputs(app: &mut App, key: KeyEvent){
     }
 
 
-    open::that(results.get(n).unwrap()).unwrap();
+    // Documents inside an archive are extracted to a temporary file first.
+    match extract_document(&results.get(n).unwrap().path) {
+        Ok(path) => open::that(path).unwrap(),
+        Err(e) => tracing::error!("Could not extract {} to open it: {e}", results.get(n).unwrap().path.display())
+    }
 }
 
@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+
+use knowledge_search::model::{base::Model, invertedmodel::InvertedModel, json_model::JsonModel};
+use proptest::prelude::*;
+
+const VOCABULARY: [&str; 8] = ["apple", "banana", "cherry", "date", "elder", "fig", "grape", "honey"];
+const DOCUMENTS: usize = 6;
+
+#[derive(Debug, Clone)]
+enum Operation {
+    Add(usize, Vec<usize>),
+    Remove(usize)
+}
+
+fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        3 => (0..DOCUMENTS, prop::collection::vec(0..VOCABULARY.len(), 1..12)).prop_map(|(doc, words)| Operation::Add(doc, words)),
+        1 => (0..DOCUMENTS).prop_map(Operation::Remove),
+    ]
+}
+
+fn doc_path(doc: usize) -> PathBuf {
+    PathBuf::from(format!("/nonexistent/doc{doc}.txt"))
+}
+
+fn content(words: &[usize]) -> Vec<char> {
+    words.iter().map(|w| VOCABULARY[*w]).collect::<Vec<&str>>().join(" ").chars().collect()
+}
+
+/// Applies the operations one by one and returns the contents of the documents that remain.
+fn apply_incrementally<M: Model>(model: &mut M, operations: &[Operation]) -> Vec<Option<Vec<usize>>> {
+    let mut state: Vec<Option<Vec<usize>>> = vec![None; DOCUMENTS];
+
+    for operation in operations {
+        match operation {
+            Operation::Add(doc, words) => {
+                model.add_document(doc_path(*doc), &content(words));
+                state[*doc] = Some(words.clone());
+            },
+            Operation::Remove(doc) => {
+                model.remove_document(doc_path(*doc));
+                state[*doc] = None;
+            }
+        }
+    }
+
+    state
+}
+
+fn rebuild<M: Model>(model: &mut M, state: &[Option<Vec<usize>>]) {
+    for (doc, words) in state.iter().enumerate() {
+        if let Some(words) = words {
+            model.add_document(doc_path(doc), &content(words));
+        }
+    }
+}
+
+fn sorted_results<M: Model>(model: &M, term: &str) -> Vec<(PathBuf, f64)> {
+    let mut results = model.search_simple(&term.chars().collect::<Vec<char>>());
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+    results
+}
+
+fn assert_same_results<M: Model>(incremental: &M, fresh: &M) -> Result<(), TestCaseError> {
+    prop_assert_eq!(incremental.document_count(), fresh.document_count());
+
+    for term in VOCABULARY {
+        let left = sorted_results(incremental, term);
+        let right = sorted_results(fresh, term);
+
+        prop_assert_eq!(left.len(), right.len(), "different hits for {}", term);
+        for ((left_path, left_rank), (right_path, right_rank)) in left.iter().zip(right.iter()) {
+            prop_assert_eq!(left_path, right_path);
+            prop_assert!((left_rank - right_rank).abs() < 1e-9, "rank of {} drifted for {}", left_path.display(), term);
+        }
+    }
+
+    Ok(())
+}
+
+const DIRECTORIES: usize = 3;
+
+fn nested_doc_path(doc: usize) -> PathBuf {
+    PathBuf::from(format!("/nonexistent/dir{}/doc{doc}.txt", doc % DIRECTORIES))
+}
+
+/// Removes one directory with `remove_prefix` and compares the result with a model built without it.
+fn check_remove_prefix<M: Model + Default>(docs: &[Vec<usize>], dir: usize) -> Result<(), TestCaseError> {
+    let prefix = PathBuf::from(format!("/nonexistent/dir{dir}"));
+
+    let mut incremental = M::default();
+    for (doc, words) in docs.iter().enumerate() {
+        incremental.add_document(nested_doc_path(doc), &content(words));
+    }
+    let removed = incremental.remove_prefix(&prefix);
+
+    let mut fresh = M::default();
+    for (doc, words) in docs.iter().enumerate() {
+        if doc % DIRECTORIES != dir {
+            fresh.add_document(nested_doc_path(doc), &content(words));
+        }
+    }
+
+    prop_assert_eq!(removed, docs.len() - fresh.document_count());
+    assert_same_results(&incremental, &fresh)
+}
+
+/// Renames one directory with `rename_path`, also onto itself or into itself, and compares the result
+/// with a model built at the new paths.
+fn check_rename<M: Model + Default>(docs: &[Vec<usize>], from: usize, to: &str) -> Result<(), TestCaseError> {
+    let from_dir = PathBuf::from(format!("/nonexistent/dir{from}"));
+    let to_dir = PathBuf::from(to);
+    let renamed = |doc: usize| match nested_doc_path(doc).strip_prefix(&from_dir) {
+        Ok(rest) => to_dir.join(rest),
+        Err(_) => nested_doc_path(doc)
+    };
+
+    let mut incremental = M::default();
+    for (doc, words) in docs.iter().enumerate() {
+        incremental.add_document(nested_doc_path(doc), &content(words));
+    }
+    let moved = incremental.rename_path(&from_dir, &to_dir);
+
+    let mut fresh = M::default();
+    for (doc, words) in docs.iter().enumerate() {
+        fresh.add_document(renamed(doc), &content(words));
+    }
+
+    let expected = (0..docs.len()).filter(|doc| renamed(*doc) != nested_doc_path(*doc)).count();
+    prop_assert_eq!(moved, expected);
+    assert_same_results(&incremental, &fresh)
+}
+
+proptest! {
+    #[test]
+    fn rename_path_matches_rebuild(docs in prop::collection::vec(prop::collection::vec(0..VOCABULARY.len(), 1..12), 1..DOCUMENTS), from in 0..DIRECTORIES, to in 0..DIRECTORIES, nested in any::<bool>()) {
+        let to = if nested { format!("/nonexistent/dir{from}/moved") } else { format!("/nonexistent/dir{to}") };
+        check_rename::<InvertedModel>(&docs, from, &to)?;
+        check_rename::<JsonModel>(&docs, from, &to)?;
+    }
+
+    #[test]
+    fn remove_prefix_matches_rebuild(docs in prop::collection::vec(prop::collection::vec(0..VOCABULARY.len(), 1..12), 1..DOCUMENTS), dir in 0..DIRECTORIES) {
+        check_remove_prefix::<InvertedModel>(&docs, dir)?;
+        check_remove_prefix::<JsonModel>(&docs, dir)?;
+    }
+
+
+    #[test]
+    fn inverted_model_matches_rebuild(operations in prop::collection::vec(operation(), 0..40)) {
+        let mut incremental = InvertedModel::new();
+        let state = apply_incrementally(&mut incremental, &operations);
+
+        let mut fresh = InvertedModel::new();
+        rebuild(&mut fresh, &state);
+
+        assert_same_results(&incremental, &fresh)?;
+        prop_assert_eq!(incremental.term_count(), fresh.term_count());
+    }
+
+    #[test]
+    fn json_model_matches_rebuild(operations in prop::collection::vec(operation(), 0..40)) {
+        let mut incremental = JsonModel::new();
+        let state = apply_incrementally(&mut incremental, &operations);
+
+        let mut fresh = JsonModel::new();
+        rebuild(&mut fresh, &state);
+
+        assert_same_results(&incremental, &fresh)?;
+        prop_assert_eq!(incremental.term_count(), fresh.term_count());
+    }
+
+    #[test]
+    fn delete_removed_files_matches_rebuild(docs in prop::collection::vec(prop::collection::vec(0..VOCABULARY.len(), 1..12), 1..DOCUMENTS), removed in prop::collection::vec(any::<bool>(), DOCUMENTS)) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |doc: usize| dir.path().join(format!("doc{doc}.txt"));
+
+        let mut incremental = InvertedModel::new();
+        for (doc, words) in docs.iter().enumerate() {
+            std::fs::write(path(doc), content(words).iter().collect::<String>()).unwrap();
+            incremental.add_document(path(doc), &content(words));
+        }
+
+        let mut fresh = InvertedModel::new();
+        for (doc, words) in docs.iter().enumerate() {
+            if removed[doc] {
+                std::fs::remove_file(path(doc)).unwrap();
+            }else{
+                fresh.add_document(path(doc), &content(words));
+            }
+        }
+
+        incremental.delete_removed_files();
+
+        assert_same_results(&incremental, &fresh)?;
+        prop_assert_eq!(incremental.term_count(), fresh.term_count());
+    }
+}